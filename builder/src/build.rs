@@ -1,22 +1,107 @@
-use risc0_zkvm::{MemoryImage, Program, MEM_SIZE, PAGE_SIZE};
-use serde::{Deserialize, Serialize};
-use std::env::{self, temp_dir};
+use std::env::temp_dir;
 use std::fs::{self, remove_dir_all};
-use std::process::Command;
+use std::path::Path;
+use std::process::{Command, Stdio};
 
+use crate::cache::{cache_key, BuildCache};
+use crate::package::{compute_image_id, GuestKind, GuestOptions};
 use crate::templates::templates::{
     WASM_BUILD_TEMPLATE_BUILD_RS, WASM_BUILD_TEMPLATE_CARGO_TOML,
     WASM_BUILD_TEMPLATE_GUEST_CARGO_TOML, WASM_BUILD_TEMPLATE_GUEST_MAIN_RS,
-    WASM_BUILD_TEMPLATE_LIB_RS,
+    WASM_BUILD_TEMPLATE_LIB_RS, WASM_BUILD_TEMPLATE_UNIVERSAL_GUEST_MAIN_RS,
 };
 use crate::DynType;
 
-pub async fn generate_wasm_elf_binaries(
+/// Runs cargo in `project_dir`. With `verbose` its output is streamed to the terminal,
+/// otherwise it is captured and only shown when the command fails.
+fn run_cargo(
+    project_dir: &Path,
+    args: &[&str],
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = Command::new("cargo");
+    command.args(args).current_dir(project_dir);
+
+    let command_line = format!("cargo {}", args.join(" "));
+    if verbose {
+        println!("Running `{}`", command_line);
+        let status = command
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|err| format!("Failed to run `{}`: {}", command_line, err))?;
+
+        if !status.success() {
+            return Err(format!("`{}` failed with {}.", command_line, status).into());
+        }
+    } else {
+        let output = command
+            .output()
+            .map_err(|err| format!("Failed to run `{}`: {}", command_line, err))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "`{}` failed with {}:\n\n{}{}\nRun with --verbose to see the full build log.",
+                command_line,
+                output.status,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Variant of the guest's `WasmValue` holding a value of the given type.
+fn wasm_value_variant(dyn_type: &DynType) -> &'static str {
+    match dyn_type {
+        DynType::I32 | DynType::Integer => "I32",
+        DynType::I64 => "I64",
+        DynType::F32 | DynType::Float => "F32",
+        DynType::F64 => "F64",
+    }
+}
+
+/// The guest project for a signature, as paths relative to the project root.
+fn project_files(
     method: &String,
     argument_type: &Vec<DynType>,
     result_type: &DynType,
-) -> Result<(String, Vec<u8>), Box<dyn std::error::Error>> {
-    println!("Building ...\n");
+    options: &GuestOptions,
+) -> Vec<(&'static str, String)> {
+    let fuel_limit_str: String = format!("{:?}", options.limits.fuel);
+    let max_memory_bytes_str: String = format!("{:?}", options.limits.max_memory_bytes);
+    let host_imports_str: String = options
+        .imports
+        .iter()
+        .map(|import| format!("{:?}", import.name()))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut files: Vec<(&'static str, String)> = vec![
+        ("Cargo.toml", WASM_BUILD_TEMPLATE_CARGO_TOML!().into()),
+        ("src/lib.rs", WASM_BUILD_TEMPLATE_LIB_RS!().into()),
+        ("build.rs", WASM_BUILD_TEMPLATE_BUILD_RS!().into()),
+    ];
+
+    // The universal guest takes everything but its limits and imports from input,
+    // so it is the same for every signature
+    if options.guest == GuestKind::Universal {
+        files.push((
+            "guest/Cargo.toml",
+            WASM_BUILD_TEMPLATE_GUEST_CARGO_TOML!().into(),
+        ));
+        files.push((
+            "guest/src/main.rs",
+            format!(
+                WASM_BUILD_TEMPLATE_UNIVERSAL_GUEST_MAIN_RS!(),
+                fuel_limit_str, max_memory_bytes_str, host_imports_str
+            ),
+        ));
+        return files;
+    }
 
     // Prepare arguments
     let argument_type_val: Vec<String> = argument_type
@@ -32,133 +117,116 @@ pub async fn generate_wasm_elf_binaries(
         argument_type_val.first().unwrap().to_string()
     };
 
-    // Create a temporary directory to hold the Cargo project
-    let temp_dir = temp_dir();
-    let dir_name = format!("bls_{}", rand::random::<u64>());
-    let project_dir = temp_dir.join(&dir_name);
+    files.push((
+        "guest/Cargo.toml",
+        WASM_BUILD_TEMPLATE_GUEST_CARGO_TOML!().into(),
+    ));
+    // Journal values for the committed call, e.g. `vec![WasmValue::I32(wasm_params.0)]`
+    let arguments_str: String = if argument_type.len() > 1 {
+        let values: Vec<String> = argument_type
+            .iter()
+            .enumerate()
+            .map(|(i, t)| format!("WasmValue::{}(wasm_params.{})", wasm_value_variant(t), i))
+            .collect();
+        format!("vec![{}]", values.join(", "))
+    } else {
+        format!(
+            "vec![WasmValue::{}(wasm_params)]",
+            wasm_value_variant(argument_type.first().unwrap())
+        )
+    };
+    let result_str: String = format!("WasmValue::{}(res)", wasm_value_variant(result_type));
 
-    // Create the Cargo project structure
-    let src_dir = project_dir.join("src");
-    let lib_rs_path = src_dir.join("lib.rs");
-    let build_rs_path = project_dir.join("build.rs");
-    let cargo_toml_path: std::path::PathBuf = project_dir.join("Cargo.toml");
-    let guest_dir = project_dir.join("guest");
-    let guest_src_dir = guest_dir.join("src");
-    let guest_main_rs_path = guest_src_dir.join("main.rs");
-    let guest_cargo_toml_path: std::path::PathBuf = guest_dir.join("Cargo.toml");
-
-    // Write the Rust code to a file
-    fs::create_dir_all(&src_dir)?;
-    fs::create_dir_all(&guest_src_dir)?;
-    fs::write(&cargo_toml_path, WASM_BUILD_TEMPLATE_CARGO_TOML!())?;
-    fs::write(&lib_rs_path, WASM_BUILD_TEMPLATE_LIB_RS!())?;
-    fs::write(&build_rs_path, WASM_BUILD_TEMPLATE_BUILD_RS!())?;
-    fs::write(
-        &guest_cargo_toml_path,
-        WASM_BUILD_TEMPLATE_GUEST_CARGO_TOML!(),
-    )?;
-    fs::write(
-        &guest_main_rs_path,
+    files.push((
+        "guest/src/main.rs",
         format!(
             WASM_BUILD_TEMPLATE_GUEST_MAIN_RS!(),
-            method, argument_type_str, result_type_str
+            method,
+            argument_type_str,
+            result_type_str,
+            fuel_limit_str,
+            max_memory_bytes_str,
+            host_imports_str,
+            result_str,
+            arguments_str
         ),
-    )?;
-
-    // Initialize Cargo project (cargo init)
-    Command::new("cargo")
-        .arg("init")
-        .arg("--bin")
-        .current_dir(&project_dir)
-        .output()
-        .expect("Failed to initialize Cargo project");
-
-    // Compile the project (cargo build)
-    Command::new("cargo")
-        .arg("build")
-        .arg("--release")
-        .current_dir(&project_dir)
-        .output()
-        .expect("Failed to compile project");
-
-    // Find the path to the compiled binary
-    let target_dir = project_dir.join("target");
-    let release_id = target_dir
-        .join("riscv-guest")
-        .join("riscv32im-risc0-zkvm-elf")
-        .join("release");
-    let compiled_binary = release_id.join("wasm-guest");
-
-    let elf_file: Vec<u8> = fs::read(compiled_binary).expect("Unable to locate ELF binaries.");
-    let program: Program =
-        Program::load_elf(&elf_file, MEM_SIZE as u32).expect("Failed to load ELF binaries.");
-    let image: MemoryImage = MemoryImage::new(&program, PAGE_SIZE as u32)?;
-    let image_id: String = hex::encode(image.compute_id());
+    ));
 
-    // @TODO Clean up the temporary directory
-    remove_dir_all(project_dir).expect("Unable to remove temp directory");
-
-    Ok((image_id, elf_file))
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Manifest {
-    wasm_path: String,
-    elf_path: String,
-    elf_id: String,
-    method: String,
-    argument_type: Vec<DynType>,
-    result_type: DynType,
+    files
 }
 
-pub async fn upload_package_to_ipfs(
-    image_id: &String,
-    image: &Vec<u8>,
-    wasm: Option<&Vec<u8>>,
+pub async fn generate_wasm_elf_binaries(
     method: &String,
     argument_type: &Vec<DynType>,
     result_type: &DynType,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let temp_dir = temp_dir();
-    let dir_name = format!("bls_{}", rand::random::<u64>());
-    let package_dir = temp_dir.join(&dir_name);
-    fs::create_dir_all(&package_dir)?;
+    options: &GuestOptions,
+    cache: Option<&BuildCache>,
+    verbose: bool,
+) -> Result<(String, Vec<u8>), Box<dyn std::error::Error>> {
+    let files = project_files(method, argument_type, result_type, options);
+
+    let key = cache_key(&files);
+    if let Some((image_id, elf_file)) = cache.and_then(|cache| cache.get(&key)) {
+        println!("Using cached guest build {}", &key);
+        return Ok((image_id, elf_file));
+    }
 
-    let elf_file_name = "elf";
-    let wasm_file_name = "zk.wasm";
+    println!("Building ...\n");
 
-    // Save files in temp directory
-    fs::write(package_dir.join(elf_file_name), image).unwrap();
+    // Create a temporary directory to hold the Cargo project
+    let temp_dir = temp_dir();
+    let dir_name = format!("bls_{}", rand::random::<u64>());
+    let project_dir = temp_dir.join(&dir_name);
 
-    if let Some(wasm) = wasm {
-        fs::write(package_dir.join(wasm_file_name), wasm).unwrap();
+    // Write the Rust code to the Cargo project structure
+    for (path, content) in &files {
+        let file_path = project_dir.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file_path, content)?;
     }
 
-    let manifest = Manifest {
-        wasm_path: wasm_file_name.into(),
-        elf_path: elf_file_name.into(),
-        elf_id: image_id.to_string(),
-        method: method.to_string(),
-        argument_type: argument_type.to_vec(),
-        result_type: result_type.to_owned(),
-    };
+    // Compile the project (cargo build). The templates already form a complete
+    // package, and `cargo init` refuses to run on one, so it is not initialized.
+    // The project is kept on failure so the build can be inspected.
+    if let Err(err) = run_cargo(&project_dir, &["build", "--release"], verbose) {
+        println!("Build directory kept at {}", project_dir.display());
+        return Err(err);
+    }
 
-    let json_manifest = serde_json::to_string_pretty(&manifest).expect("JSON serialization failed");
+    // Find the path to the compiled binary
+    let target_dir = project_dir.join("target");
+    let release_id = target_dir
+        .join("riscv-guest")
+        .join("riscv32im-risc0-zkvm-elf")
+        .join("release");
+    let compiled_binary = release_id.join("wasm-guest");
 
-    fs::write(package_dir.join("manifest.json"), json_manifest)
-        .expect("Failed to write JSON to file");
+    let elf_file: Vec<u8> = fs::read(&compiled_binary).map_err(|err| {
+        format!(
+            "Unable to locate ELF binaries at {}: {}",
+            compiled_binary.display(),
+            err
+        )
+    })?;
+    let image_id: String = compute_image_id(&elf_file)?;
+
+    // The build already succeeded, a leftover directory is no reason to fail it
+    if let Err(err) = remove_dir_all(&project_dir) {
+        println!(
+            "warning: failed to remove the build directory {}: {}",
+            project_dir.display(),
+            err
+        );
+    }
 
-    let results = w3s::helper::upload_dir(
-        &package_dir.into_os_string().into_string().unwrap(),
-        None,
-        env::var("WEB3_STORAGE_TOKEN").unwrap(),
-        2,
-        None,
-        None, // if use encryption with password
-        None, // if use compression with zstd level
-    )
-    .await
-    .expect("Failed to upload.");
+    if let Some(cache) = cache {
+        // A failed store only costs the next build its shortcut
+        if let Err(err) = cache.put(&key, &image_id, &elf_file) {
+            println!("warning: failed to cache the guest build: {}", err);
+        }
+    }
 
-    Ok(results.first().unwrap().to_string())
+    Ok((image_id, elf_file))
 }
@@ -0,0 +1,182 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use filetime::{set_file_mtime, FileTime};
+use sha2::{Digest, Sha256};
+
+use crate::package::compute_image_id;
+
+const ELF_FILE_NAME: &str = "elf";
+const IMAGE_ID_FILE_NAME: &str = "image_id";
+const STAGING_MARKER: &str = ".tmp-";
+/// Staging directories younger than this may belong to a build still storing its entry.
+const STAGING_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Compiled guest ELFs, keyed by the exact project they were built from.
+///
+/// The WASM module is read by the guest at runtime, so a guest only depends on the
+/// rendered templates and one build serves every module with the same signature.
+pub struct BuildCache {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub removed: usize,
+    pub kept: usize,
+}
+
+/// `$XDG_CACHE_HOME/wasm-builder`, falling back to `~/.cache/wasm-builder`.
+pub fn default_cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("wasm-builder")
+}
+
+/// Hashes every file of a guest project, so any template change is a new key.
+pub fn cache_key(project_files: &[(&str, String)]) -> String {
+    let mut hasher = Sha256::new();
+    for (path, content) in project_files {
+        hasher.update((path.len() as u64).to_le_bytes());
+        hasher.update(path.as_bytes());
+        hasher.update((content.len() as u64).to_le_bytes());
+        hasher.update(content.as_bytes());
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+impl BuildCache {
+    pub fn new(dir: PathBuf) -> Self {
+        BuildCache { dir }
+    }
+
+    /// Returns the cached image ID and ELF, if the entry exists and is intact.
+    pub fn get(&self, key: &str) -> Option<(String, Vec<u8>)> {
+        let entry_dir = self.dir.join(key);
+        let elf_file = fs::read(entry_dir.join(ELF_FILE_NAME)).ok()?;
+        let image_id = fs::read_to_string(entry_dir.join(IMAGE_ID_FILE_NAME)).ok()?;
+
+        // A truncated or edited entry is treated as a miss and rebuilt
+        if compute_image_id(&elf_file).ok()? != image_id {
+            return None;
+        }
+
+        // Touching the image ID marks the entry as recently used for pruning; failing to
+        // is no reason to rebuild
+        let _ = set_file_mtime(entry_dir.join(IMAGE_ID_FILE_NAME), FileTime::now());
+
+        Some((image_id, elf_file))
+    }
+
+    pub fn put(&self, key: &str, image_id: &str, elf_file: &[u8]) -> Result<(), Box<dyn Error>> {
+        // Written next to the entry and renamed, so concurrent builds never see half an entry
+        let staging_dir = self.dir.join(format!(
+            "{}{}{}",
+            key,
+            STAGING_MARKER,
+            rand::random::<u64>()
+        ));
+        fs::create_dir_all(&staging_dir)?;
+        fs::write(staging_dir.join(ELF_FILE_NAME), elf_file)?;
+        fs::write(staging_dir.join(IMAGE_ID_FILE_NAME), image_id)?;
+
+        let entry_dir = self.dir.join(key);
+        if fs::rename(&staging_dir, &entry_dir).is_err() {
+            // Another build stored the same entry first
+            fs::remove_dir_all(&staging_dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes entries unused for longer than `max_age`, or every entry without one.
+    /// Staging directories are only removed once older than [`STAGING_GRACE_PERIOD`].
+    pub fn prune(&self, max_age: Option<Duration>) -> Result<PruneReport, Box<dyn Error>> {
+        let mut report = PruneReport::default();
+        if !self.dir.exists() {
+            return Ok(report);
+        }
+
+        let now = SystemTime::now();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry_dir = entry?.path();
+            if !entry_dir.is_dir() {
+                continue;
+            }
+
+            let is_staging = entry_dir.file_name().map_or(false, |name| {
+                name.to_string_lossy().contains(STAGING_MARKER)
+            });
+            if is_staging {
+                let in_flight = fs::metadata(&entry_dir)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .map_or(false, |age| age < STAGING_GRACE_PERIOD);
+                if in_flight {
+                    report.kept += 1;
+                    continue;
+                }
+            }
+
+            let last_used = fs::metadata(entry_dir.join(IMAGE_ID_FILE_NAME))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+
+            let expired = match (max_age, last_used) {
+                (Some(max_age), Some(last_used)) => now
+                    .duration_since(last_used)
+                    .map_or(false, |age| age > max_age),
+                // Without a last use this is a leftover from an interrupted build
+                _ => true,
+            };
+
+            if expired {
+                fs::remove_dir_all(&entry_dir)?;
+                report.removed += 1;
+            } else {
+                report.kept += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cache() -> BuildCache {
+        let dir = env::temp_dir().join(format!("wasm-builder-test-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        BuildCache::new(dir)
+    }
+
+    #[test]
+    fn test_prune_keeps_young_staging_dirs() {
+        let cache = test_cache();
+        let staging_dir = cache.dir.join(format!("key{}1", STAGING_MARKER));
+        fs::create_dir_all(&staging_dir).unwrap();
+        fs::write(staging_dir.join(ELF_FILE_NAME), b"elf").unwrap();
+
+        let report = cache.prune(None).unwrap();
+        assert_eq!((report.removed, report.kept), (0, 1));
+        assert!(staging_dir.exists());
+
+        let abandoned = SystemTime::now() - STAGING_GRACE_PERIOD * 2;
+        set_file_mtime(&staging_dir, FileTime::from_system_time(abandoned)).unwrap();
+
+        let report = cache.prune(None).unwrap();
+        assert_eq!((report.removed, report.kept), (1, 0));
+        assert!(!staging_dir.exists());
+
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use wasmi::core::ValueType;
+
+/// Module name WASM code imports host functions from.
+pub const HOST_MODULE: &str = "zkvm";
+
+/// A group of host functions the guest can provide to the WASM module.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HostImport {
+    /// `input_len() -> i32` and `input_read(ptr, len) -> i32` over extra private input bytes
+    Input,
+    /// `commit(ptr, len)` appends bytes to the journal
+    Commit,
+    /// `log(ptr, len)` prints a UTF-8 message from the zkVM
+    Log,
+    /// `sha256(ptr, len, out_ptr)` writes the 32 byte digest, using the zkVM accelerator
+    Sha256,
+}
+
+/// A host function: its name, parameter types and result types.
+pub type HostFunction = (&'static str, &'static [ValueType], &'static [ValueType]);
+
+const I32: ValueType = ValueType::I32;
+
+impl HostImport {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HostImport::Input => "input",
+            HostImport::Commit => "commit",
+            HostImport::Log => "log",
+            HostImport::Sha256 => "sha256",
+        }
+    }
+
+    pub fn functions(&self) -> &'static [HostFunction] {
+        match self {
+            HostImport::Input => &[
+                ("input_len", &[], &[I32]),
+                ("input_read", &[I32, I32], &[I32]),
+            ],
+            HostImport::Commit => &[("commit", &[I32, I32], &[])],
+            HostImport::Log => &[("log", &[I32, I32], &[])],
+            HostImport::Sha256 => &[("sha256", &[I32, I32, I32], &[])],
+        }
+    }
+}
+
+/// Finds the import group providing the host function `name`.
+pub fn find_host_function(name: &str) -> Option<(HostImport, &'static HostFunction)> {
+    [
+        HostImport::Input,
+        HostImport::Commit,
+        HostImport::Log,
+        HostImport::Sha256,
+    ]
+    .into_iter()
+    .find_map(|import| {
+        import
+            .functions()
+            .iter()
+            .find(|(function, _, _)| *function == name)
+            .map(|function| (import, function))
+    })
+}
@@ -1,21 +1,35 @@
 mod build;
+mod cache;
+mod host;
+mod package;
+mod storage;
 mod templates;
 mod utils;
+mod wasm;
 
-use std::{fs::File, io::Read, str::FromStr};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use build::generate_wasm_elf_binaries;
-use clap::{Parser, ValueEnum};
+use cache::{default_cache_dir, BuildCache};
+use clap::{Parser, Subcommand, ValueEnum};
+use host::HostImport;
+use package::{GuestKind, GuestOptions, Package, WasmLimits};
 use serde::{Deserialize, Serialize};
+use storage::{fetch_package, upload_package, StorageArgs};
 use utils::is_wasm_file;
+use wasm::{read_export_signature, resolve_signature, validate_module, Severity};
 
-use crate::build::upload_package_to_ipfs;
-
-fn build_elf(_: &Args) {
+fn build_elf(_: &BuildArgs) {
     println!("TODO: ELF Builds");
 }
 
-async fn build_wasm(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+async fn build_wasm(args: &BuildArgs) -> Result<(), Box<dyn std::error::Error>> {
     if !is_wasm_file(&args.path) {
         return Err("Path is not a wasm file.".into());
     }
@@ -26,23 +40,140 @@ async fn build_wasm(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
     file.read_to_end(&mut wasm_file)
         .expect("Failed to load WASM file.");
 
-    let (image_id, image) =
-        generate_wasm_elf_binaries(&args.method, &args.argument_type, &args.result_type)
-            .await
-            .expect("Unable to generate WASM Elf binaries.");
+    // Validate the module before spending minutes on a guest build
+    let diagnostics = validate_module(&wasm_file, &args.method, &args.import);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err("WASM module failed validation.".into());
+    }
+
+    // Check the given types against the exported function
+    let signature = resolve_signature(
+        read_export_signature(&wasm_file, &args.method)?,
+        &args.argument_type,
+        args.result_type.as_ref(),
+    )?;
+    println!("Using {} {}", &args.method, signature.to_string());
+
+    // Sorted so the order of `--import` flags does not change the image ID
+    let mut imports = args.import.clone();
+    imports.sort();
+    imports.dedup();
+    let options = GuestOptions {
+        guest: if args.universal {
+            GuestKind::Universal
+        } else {
+            GuestKind::Typed
+        },
+        limits: WasmLimits {
+            fuel: args.fuel,
+            max_memory_bytes: args.max_memory_bytes,
+        },
+        imports,
+    };
+    let cache = (!args.no_cache).then(|| BuildCache::new(args.cache.cache_dir.clone()));
+    let (image_id, image) = generate_wasm_elf_binaries(
+        &args.method,
+        &signature.argument_type,
+        &signature.result_type,
+        &options,
+        cache.as_ref(),
+        args.verbose,
+    )
+    .await?;
 
-    let cid = upload_package_to_ipfs(
+    let package = Package::new(
         &image_id,
         &image,
         Some(&wasm_file),
         &args.method,
-        &args.argument_type,
-        &args.result_type,
-    )
-    .await
-    .unwrap();
+        &signature.argument_type,
+        &signature.result_type,
+        &options,
+    );
+    package.write(&args.out)?;
 
-    println!("\n{}", &cid);
+    println!("\nPackage written to {}", args.out.display());
+    println!("Image ID: {}", &image_id);
+    println!("CID: {}", package.cid()?);
+
+    Ok(())
+}
+
+async fn publish(args: &PublishArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // Refuse to upload a package the server would reject
+    let package = Package::read_dir(&args.dir)?;
+    package.verify()?;
+    let expected_cid = package.cid()?;
+
+    let cid = upload_package(&args.storage, &args.dir).await?;
+    if cid != expected_cid {
+        println!(
+            "warning: uploaded CID differs from the computed CID {}",
+            &expected_cid
+        );
+    }
+
+    println!("{}", &cid);
+
+    Ok(())
+}
+
+/// Reads a package from a directory, or fetches it when `target` is not one.
+async fn load_package(
+    target: &String,
+    storage: &StorageArgs,
+) -> Result<Package, Box<dyn std::error::Error>> {
+    let path = Path::new(target);
+    if path.is_dir() {
+        Package::read_dir(path)
+    } else {
+        fetch_package(storage, target).await
+    }
+}
+
+async fn inspect(args: &InspectArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let package = load_package(&args.target, &args.storage).await?;
+
+    println!("{}", serde_json::to_string_pretty(&package.manifest)?);
+    println!("\nFiles:");
+    for (name, content) in &package.files {
+        println!("  {} ({} bytes)", name, content.len());
+    }
+
+    let image_id = package.compute_image_id()?;
+    let status = if image_id.eq_ignore_ascii_case(&package.manifest.elf_id) {
+        "matches manifest"
+    } else {
+        "DOES NOT match manifest"
+    };
+    println!("\nImage ID: {} ({})", image_id, status);
+    println!("CID: {}", package.cid()?);
+
+    Ok(())
+}
+
+fn prune_cache(args: &PruneArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let max_age = args
+        .max_age_days
+        .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    let report = BuildCache::new(args.cache.cache_dir.clone()).prune(max_age)?;
+
+    println!(
+        "Removed {} cached guest builds, kept {}",
+        report.removed, report.kept
+    );
+
+    Ok(())
+}
+
+async fn verify_package(args: &InspectArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let package = load_package(&args.target, &args.storage).await?;
+    let image_id = package.verify()?;
+
+    println!("OK: elf_id matches the ELF's image ID {}", image_id);
 
     Ok(())
 }
@@ -94,10 +225,44 @@ impl FromStr for DynType {
     }
 }
 
-/// Build Risc0 binaries with a WASM file
+/// Build and publish Risc0 packages for WASM files
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build a package into a local directory
+    Build(BuildArgs),
+    /// Upload a package directory
+    Publish(PublishArgs),
+    /// Print a package's manifest and recompute its image ID
+    Inspect(InspectArgs),
+    /// Check that a package's manifest matches its ELF
+    VerifyPackage(InspectArgs),
+    /// Manage the cache of compiled guests
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Remove cached guest builds
+    Prune(PruneArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CacheArgs {
+    /// Directory compiled guests are cached in
+    #[arg(long, env = "WASM_BUILDER_CACHE_DIR", default_value_os_t = default_cache_dir())]
+    cache_dir: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct BuildArgs {
     /// Type of source package
     #[arg(value_enum)]
     #[arg(long)]
@@ -110,23 +275,96 @@ struct Args {
     #[arg(short, long, default_value = "zkmain")]
     method: String,
 
+    /// Inferred from the exported function when omitted
     #[arg(short, long)]
     argument_type: Vec<DynType>,
 
+    /// Inferred from the exported function when omitted
+    #[arg(short, long)]
+    result_type: Option<DynType>,
+
+    /// Directory the package is written to
+    #[arg(short, long, default_value = "package")]
+    out: PathBuf,
+
+    /// Use the universal guest, whose image ID is the same for every module and signature
+    #[arg(long)]
+    universal: bool,
+
+    /// Fuel the WASM function may consume before it is stopped, unlimited when omitted.
+    /// Limits are compiled into the guest, so they change its image ID
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    fuel: Option<u64>,
+
+    /// Maximum size of the WASM linear memory, unlimited when omitted
+    #[arg(long)]
+    max_memory_bytes: Option<usize>,
+
+    /// Host functions the module may import from `zkvm`, repeat for each group
+    #[arg(long, value_enum)]
+    import: Vec<HostImport>,
+
+    /// Show the full cargo output of the guest build
     #[arg(short, long)]
-    result_type: DynType,
+    verbose: bool,
+
+    /// Always compile the guest, without reading or writing the cache
+    #[arg(long)]
+    no_cache: bool,
+
+    #[command(flatten)]
+    cache: CacheArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct PruneArgs {
+    /// Only remove builds unused for this many days, instead of all of them
+    #[arg(long)]
+    max_age_days: Option<u64>,
+
+    #[command(flatten)]
+    cache: CacheArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct PublishArgs {
+    /// Package directory, as written by `build`
+    dir: PathBuf,
+
+    #[command(flatten)]
+    storage: StorageArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct InspectArgs {
+    /// Package directory or CID
+    target: String,
+
+    #[command(flatten)]
+    storage: StorageArgs,
 }
 
 #[tokio::main]
 async fn main() {
-    let args: Args = Args::parse();
+    let cli: Cli = Cli::parse();
 
-    match args.mode {
-        Mode::ELF => {
-            build_elf(&args);
-        }
-        Mode::WASM => {
-            build_wasm(&args).await.unwrap();
-        }
+    let result = match &cli.command {
+        Command::Build(args) => match args.mode {
+            Mode::ELF => {
+                build_elf(args);
+                Ok(())
+            }
+            Mode::WASM => build_wasm(args).await,
+        },
+        Command::Publish(args) => publish(args).await,
+        Command::Inspect(args) => inspect(args).await,
+        Command::VerifyPackage(args) => verify_package(args).await,
+        Command::Cache(CacheCommand::Prune(args)) => prune_cache(args),
     };
+
+    // Print with Display, cargo diagnostics in the error span multiple lines
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use risc0_zkvm::{MemoryImage, Program, MEM_SIZE, PAGE_SIZE};
+use serde::{Deserialize, Serialize};
+use unixfs::directory_cid;
+
+use crate::host::HostImport;
+use crate::DynType;
+
+pub const ELF_FILE_NAME: &str = "elf";
+pub const WASM_FILE_NAME: &str = "zk.wasm";
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Which guest template a package was built with.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GuestKind {
+    /// Built for one method and signature
+    #[default]
+    Typed,
+    /// Reads the method and arguments from input, one image ID for every module
+    Universal,
+}
+
+/// What a package's guest commits to its journal.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalFormat {
+    /// Only the result, as packages built before the module hash was committed
+    #[default]
+    Result,
+    /// The SHA-256 of the WASM module, the method and the arguments with the result
+    Wasm,
+}
+
+/// Resource limits the guest enforces on the WASM function.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct WasmLimits {
+    /// wasmi fuel, roughly one unit per executed instruction
+    pub fuel: Option<u64>,
+    pub max_memory_bytes: Option<usize>,
+}
+
+/// How the guest of a package is generated, on top of the exported signature.
+#[derive(Debug, Clone, Default)]
+pub struct GuestOptions {
+    pub guest: GuestKind,
+    pub limits: WasmLimits,
+    /// Host function groups linked into the module, sorted and without duplicates
+    pub imports: Vec<HostImport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub wasm_path: String,
+    pub elf_path: String,
+    pub elf_id: String,
+    pub method: String,
+    pub argument_type: Vec<DynType>,
+    pub result_type: DynType,
+    #[serde(default)]
+    pub guest: GuestKind,
+    #[serde(default)]
+    pub journal: JournalFormat,
+    #[serde(default)]
+    pub limits: WasmLimits,
+    /// Host functions the module imports; `input` means sessions may send private input
+    #[serde(default)]
+    pub imports: Vec<HostImport>,
+}
+
+/// A manifest with the files it references, as built, uploaded or fetched back.
+#[derive(Debug)]
+pub struct Package {
+    pub manifest: Manifest,
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+pub fn compute_image_id(elf: &[u8]) -> Result<String, Box<dyn Error>> {
+    let program: Program = Program::load_elf(elf, MEM_SIZE as u32)
+        .map_err(|err| format!("Failed to load ELF binaries: {}", err))?;
+    let image: MemoryImage = MemoryImage::new(&program, PAGE_SIZE as u32)?;
+
+    Ok(hex::encode(image.compute_id()))
+}
+
+impl Package {
+    pub fn new(
+        image_id: &String,
+        image: &Vec<u8>,
+        wasm: Option<&Vec<u8>>,
+        method: &String,
+        argument_type: &Vec<DynType>,
+        result_type: &DynType,
+        options: &GuestOptions,
+    ) -> Self {
+        let manifest = Manifest {
+            wasm_path: WASM_FILE_NAME.into(),
+            elf_path: ELF_FILE_NAME.into(),
+            elf_id: image_id.to_string(),
+            method: method.to_string(),
+            argument_type: argument_type.to_vec(),
+            result_type: result_type.to_owned(),
+            guest: options.guest,
+            journal: JournalFormat::Wasm,
+            limits: options.limits,
+            imports: options.imports.clone(),
+        };
+
+        let mut files = BTreeMap::new();
+        files.insert(ELF_FILE_NAME.to_string(), image.clone());
+        if let Some(wasm) = wasm {
+            files.insert(WASM_FILE_NAME.to_string(), wasm.clone());
+        }
+
+        Package { manifest, files }
+    }
+
+    /// Splits a flat listing of package files into the manifest and the rest.
+    pub fn from_files(mut files: BTreeMap<String, Vec<u8>>) -> Result<Self, Box<dyn Error>> {
+        let manifest_file = files
+            .remove(MANIFEST_FILE_NAME)
+            .ok_or("Package has no manifest.json.")?;
+        let manifest: Manifest = serde_json::from_slice(&manifest_file)
+            .map_err(|err| format!("Invalid manifest.json: {}", err))?;
+
+        Ok(Package { manifest, files })
+    }
+
+    pub fn read_dir(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                files.insert(name, fs::read(entry.path())?);
+            }
+        }
+
+        Package::from_files(files)
+    }
+
+    /// All files of the package, including the serialized manifest.
+    pub fn all_files(&self) -> Result<Vec<(&str, Vec<u8>)>, Box<dyn Error>> {
+        let mut files: Vec<(&str, Vec<u8>)> = self
+            .files
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.clone()))
+            .collect();
+        files.push((
+            MANIFEST_FILE_NAME,
+            serde_json::to_vec_pretty(&self.manifest)?,
+        ));
+
+        Ok(files)
+    }
+
+    /// The CID the package gets once it is uploaded.
+    pub fn cid(&self) -> Result<String, Box<dyn Error>> {
+        let files = self.all_files()?;
+        let files: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(name, content)| (*name, content.as_slice()))
+            .collect();
+
+        Ok(directory_cid(&files)?.to_string())
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        for (name, content) in self.all_files()? {
+            fs::write(dir.join(name), content)
+                .map_err(|err| format!("Failed to write {}: {}", dir.join(name).display(), err))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn elf(&self) -> Result<&Vec<u8>, Box<dyn Error>> {
+        self.files
+            .get(&self.manifest.elf_path)
+            .ok_or_else(|| format!("Package is missing {}.", self.manifest.elf_path).into())
+    }
+
+    /// Recomputes the image ID from the ELF, which is what proofs are verified against.
+    pub fn compute_image_id(&self) -> Result<String, Box<dyn Error>> {
+        compute_image_id(self.elf()?)
+    }
+
+    /// Fails unless the manifest's `elf_id` is the image ID of the packaged ELF.
+    pub fn verify(&self) -> Result<String, Box<dyn Error>> {
+        let image_id = self.compute_image_id()?;
+        if !image_id.eq_ignore_ascii_case(&self.manifest.elf_id) {
+            return Err(format!(
+                "Manifest elf_id {} does not match the ELF's image ID {}.",
+                self.manifest.elf_id, image_id
+            )
+            .into());
+        }
+
+        Ok(image_id)
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use clap::ValueEnum;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+
+use crate::package::Package;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum StorageBackend {
+    /// web3.storage, authenticated with a token
+    Web3Storage,
+    /// The `add` endpoint of an IPFS HTTP API, e.g. a local Kubo node
+    Ipfs,
+}
+
+/// Where packages are uploaded to and fetched from.
+#[derive(clap::Args, Debug)]
+pub struct StorageArgs {
+    /// Storage backend packages are uploaded to
+    #[arg(long, value_enum, default_value_t = StorageBackend::Web3Storage)]
+    pub storage: StorageBackend,
+
+    #[arg(long, env = "WEB3_STORAGE_TOKEN", hide_env_values = true)]
+    pub web3_storage_token: Option<String>,
+
+    /// IPFS HTTP API used to fetch packages, and to upload them with `--storage ipfs`
+    #[arg(long, env = "IPFS_API_URL", default_value = "https://dweb.link/api/v0")]
+    pub ipfs_api_url: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+struct IPFSObjectLink {
+    Name: String,
+    Hash: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+struct IPFSObject {
+    Links: Vec<IPFSObjectLink>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+struct IPFSListResponse {
+    Objects: Vec<IPFSObject>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+struct IPFSAddResponse {
+    Name: String,
+    Hash: String,
+}
+
+/// Uploads a package directory and returns its CID.
+pub async fn upload_package(storage: &StorageArgs, dir: &Path) -> Result<String, Box<dyn Error>> {
+    match storage.storage {
+        StorageBackend::Web3Storage => upload_to_web3_storage(storage, dir).await,
+        StorageBackend::Ipfs => upload_to_ipfs(storage, dir).await,
+    }
+}
+
+async fn upload_to_web3_storage(
+    storage: &StorageArgs,
+    dir: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let token = storage
+        .web3_storage_token
+        .clone()
+        .ok_or("No web3.storage token, set WEB3_STORAGE_TOKEN or --web3-storage-token.")?;
+    let dir = dir.to_str().ok_or("Package path is not valid UTF-8.")?;
+
+    let results = w3s::helper::upload_dir(
+        dir, None, token, 2, None, None, // if use encryption with password
+        None, // if use compression with zstd level
+    )
+    .await
+    .map_err(|err| format!("Failed to upload to web3.storage: {:?}", err))?;
+
+    Ok(results
+        .first()
+        .ok_or("web3.storage returned no CID.")?
+        .to_string())
+}
+
+async fn upload_to_ipfs(storage: &StorageArgs, dir: &Path) -> Result<String, Box<dyn Error>> {
+    let package = Package::read_dir(dir)?;
+
+    let mut form = Form::new();
+    for (name, content) in package.all_files()? {
+        form = form.part("file", Part::bytes(content).file_name(name.to_string()));
+    }
+
+    // Same layout as `Package::cid`, so the printed CID matches the uploaded one
+    let response = Client::new()
+        .post(format!(
+            "{}/add?wrap-with-directory=true&cid-version=1&raw-leaves=true&chunker=size-1048576",
+            storage.ipfs_api_url
+        ))
+        .multipart(form)
+        .send()
+        .await?;
+
+    if response.status() != StatusCode::OK {
+        return Err(format!("Failed to upload to IPFS: {}", response.status()).into());
+    }
+
+    // One line per added file, the wrapping directory has an empty name
+    let body = response.text().await?;
+    for line in body.lines() {
+        let added: IPFSAddResponse = serde_json::from_str(line)?;
+        if added.Name.is_empty() {
+            return Ok(added.Hash);
+        }
+    }
+
+    Err("IPFS returned no directory CID.".into())
+}
+
+async fn download(storage: &StorageArgs, cid: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let response = Client::new()
+        .get(format!("{}/cat/{}", storage.ipfs_api_url, cid))
+        .send()
+        .await?;
+
+    if response.status() != StatusCode::OK {
+        return Err(format!("Failed to download {} from IPFS.", cid).into());
+    }
+
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Fetches every file of an uploaded package.
+pub async fn fetch_package(storage: &StorageArgs, cid: &str) -> Result<Package, Box<dyn Error>> {
+    let response = Client::new()
+        .get(format!("{}/ls/{}", storage.ipfs_api_url, cid))
+        .send()
+        .await?;
+
+    if response.status() != StatusCode::OK {
+        return Err(format!("Failed to list {} on IPFS.", cid).into());
+    }
+
+    let listing: IPFSListResponse = response.json().await?;
+    let links = &listing
+        .Objects
+        .first()
+        .ok_or("IPFS returned an empty listing.")?
+        .Links;
+
+    let mut files = BTreeMap::new();
+    for link in links {
+        files.insert(link.Name.clone(), download(storage, &link.Hash).await?);
+    }
+
+    Package::from_files(files)
+}
@@ -37,15 +37,190 @@ edition = "2021"
 
 [dependencies]
 risc0-zkvm = { version = "0.17.0", default-features = false, features = ["std"] }
+serde = { version = "1.0", features = ["derive"] }
 wasmi = "0.31.0""#
         };
     }
+
+    /// Everything both guest templates share, from `WasmOutcome` to `WasmJournal`.
+    macro_rules! WASM_GUEST_PRELUDE {
+        () => {
+            r#"#[derive(Debug, Serialize)]
+enum WasmOutcome {{
+    Returned(WasmValue),
+    OutOfFuel,
+    /// The call returned something other than a single number, which can't be committed
+    Unsupported(String),
+}}
+
+/// Whether a failure is the trap of running out of fuel, rather than any other error.
+trait OutOfFuel {{
+    fn is_out_of_fuel(&self) -> bool;
+}}
+
+impl OutOfFuel for Trap {{
+    fn is_out_of_fuel(&self) -> bool {{
+        matches!(self.trap_code(), Some(TrapCode::OutOfFuel))
+    }}
+}}
+
+impl OutOfFuel for Error {{
+    fn is_out_of_fuel(&self) -> bool {{
+        matches!(self, Error::Trap(trap) if trap.is_out_of_fuel())
+    }}
+}}
+
+struct HostState {{
+    limits: StoreLimits,
+    input: Vec<u8>,
+    input_offset: usize,
+    committed: Vec<u8>,
+}}
+
+fn new_store(engine: &Engine, input: Vec<u8>) -> Store<HostState> {{
+    let mut limits = StoreLimitsBuilder::new();
+    if let Some(max_memory_bytes) = MAX_MEMORY_BYTES {{
+        limits = limits.memory_size(max_memory_bytes);
+    }}
+
+    let state = HostState {{
+        limits: limits.build(),
+        input,
+        input_offset: 0,
+        committed: Vec::new(),
+    }};
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+    if let Some(fuel) = FUEL_LIMIT {{
+        store.add_fuel(fuel).expect("Failed to add fuel.");
+    }}
+
+    store
+}}
+
+fn memory(caller: &Caller<'_, HostState>) -> Memory {{
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .expect("Host functions need the module to export `memory`.")
+}}
+
+fn read_bytes(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Vec<u8> {{
+    let mut bytes = vec![0; len as u32 as usize];
+    memory(caller)
+        .read(caller, ptr as u32 as usize, &mut bytes)
+        .expect("Out of bounds memory read.");
+    bytes
+}}
+
+fn write_bytes(caller: &mut Caller<'_, HostState>, ptr: i32, bytes: &[u8]) {{
+    memory(caller)
+        .write(caller, ptr as u32 as usize, bytes)
+        .expect("Out of bounds memory write.");
+}}
+
+/// Copies up to `len` bytes of the remaining private input to `ptr`, trapping on a length
+/// that isn't a valid size rather than wrapping it.
+fn input_read(mut caller: Caller<'_, HostState>, ptr: i32, len: i32) -> Result<i32, Trap> {{
+    let len = usize::try_from(len).map_err(|_| Trap::new("input_read length is negative."))?;
+    let state = caller.data();
+    let start = state.input_offset;
+    let end = start
+        .checked_add(len)
+        .ok_or_else(|| Trap::new("input_read length overflows."))?
+        .min(state.input.len());
+    let chunk = state.input[start..end].to_vec();
+    write_bytes(&mut caller, ptr, &chunk);
+    caller.data_mut().input_offset = end;
+    Ok(chunk.len() as i32)
+}}
+
+fn define_host_imports(linker: &mut Linker<HostState>, store: &mut Store<HostState>) {{
+    for import in HOST_IMPORTS {{
+        let functions: Vec<(&str, Func)> = match *import {{
+            "input" => vec![
+                (
+                    "input_len",
+                    Func::wrap(&mut *store, |caller: Caller<'_, HostState>| -> i32 {{
+                        let state = caller.data();
+                        (state.input.len() - state.input_offset) as i32
+                    }}),
+                ),
+                ("input_read", Func::wrap(&mut *store, input_read)),
+            ],
+            "commit" => vec![(
+                "commit",
+                Func::wrap(
+                    &mut *store,
+                    |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {{
+                        let bytes = read_bytes(&caller, ptr, len);
+                        caller.data_mut().committed.extend(bytes);
+                    }},
+                ),
+            )],
+            "log" => vec![(
+                "log",
+                Func::wrap(
+                    &mut *store,
+                    |caller: Caller<'_, HostState>, ptr: i32, len: i32| {{
+                        env::log(&String::from_utf8_lossy(&read_bytes(&caller, ptr, len)));
+                    }},
+                ),
+            )],
+            "sha256" => vec![(
+                "sha256",
+                Func::wrap(
+                    &mut *store,
+                    |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, out_ptr: i32| {{
+                        let digest = *Impl::hash_bytes(&read_bytes(&caller, ptr, len));
+                        write_bytes(&mut caller, out_ptr, digest.as_bytes());
+                    }},
+                ),
+            )],
+            _ => unreachable!(),
+        }};
+
+        for (name, func) in functions {{
+            linker
+                .define("zkvm", name, func)
+                .expect("Failed to define host function.");
+        }}
+    }}
+}}
+
+fn new_engine() -> Engine {{
+    let mut config = Config::default();
+    config.consume_fuel(FUEL_LIMIT.is_some());
+
+    Engine::new(&config)
+}}
+
+#[derive(Serialize)]
+struct WasmJournal {{
+    wasm_sha256: Digest,
+    method: String,
+    arguments: Vec<WasmValue>,
+    committed: Vec<u8>,
+    result: WasmOutcome,
+}}
+
+"#
+        };
+    }
+
     macro_rules! WASM_BUILD_TEMPLATE_GUEST_MAIN_RS {
-      () => {
-          r#"#![no_main]
-  
+        () => {
+            concat!(
+                r#"#![no_main]
+
 use risc0_zkvm::guest::env;
-use wasmi::{{Engine, Linker, Module, Store}};
+use risc0_zkvm::sha::{{Digest, Impl, Sha256}};
+use serde::Serialize;
+use wasmi::core::{{Trap, TrapCode}};
+use wasmi::{{
+    Caller, Config, Engine, Error, Extern, Func, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+}};
 
 risc0_zkvm::guest::entry!(main);
 
@@ -53,45 +228,244 @@ const WASM_NAME: &str = "{}";
 type WasmParams = {};
 type WasmResult = {};
 
-pub fn main() {{
+// Limits are part of the guest, and so of its image ID
+const FUEL_LIMIT: Option<u64> = {};
+const MAX_MEMORY_BYTES: Option<usize> = {};
+
+// Host functions the module may import from `zkvm`, as declared in the manifest
+const HOST_IMPORTS: &[&str] = &[{}];
+
+#[derive(Debug, Serialize)]
+enum WasmValue {{
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}}
+
+"#,
+                $crate::templates::templates::WASM_GUEST_PRELUDE!(),
+                r#"pub fn main() {{
     // Load environment variables
-    let wasm_name = WASM_NAME;
     let wasm_file: Vec<u8> = env::read();
     let wasm_params: WasmParams = env::read();
+    let private_input: Vec<u8> = if HOST_IMPORTS.contains(&"input") {{
+        env::read()
+    }} else {{
+        Vec::new()
+    }};
 
-    let engine = Engine::default();
+    let engine = new_engine();
     let module = Module::new(&engine, &mut &wasm_file[..]).expect("Failed to create module");
-    type HostState = i32;
 
-    let linker = <Linker<HostState>>::new(&engine);
-    let mut store = Store::new(&engine, 0);
+    let mut linker = <Linker<HostState>>::new(&engine);
+    let mut store = new_store(&engine, private_input);
+    define_host_imports(&mut linker, &mut store);
     let instance = linker
         .instantiate(&mut store, &module)
         .expect("Failed to instansitate.")
-        .start(&mut store)
-        .expect("Failed to start.");
+        .start(&mut store);
 
-    let wasm_fn = instance
-        .get_typed_func::<WasmParams, WasmResult>(&store, WASM_NAME)
-        .expect("Failed to get typed_func.");
+    // Running out of fuel is committed as an outcome, any other failure aborts the proof
+    let result = match instance {{
+        Ok(instance) => {{
+            let wasm_fn = instance
+                .get_typed_func::<WasmParams, WasmResult>(&store, WASM_NAME)
+                .expect("Failed to get typed_func.");
 
-    let res = wasm_fn
-        .call(&mut store, wasm_params)
-        .expect("Failed to call verify state");
+            match wasm_fn.call(&mut store, wasm_params) {{
+                Ok(res) => WasmOutcome::Returned({}),
+                Err(err) if err.is_out_of_fuel() => WasmOutcome::OutOfFuel,
+                Err(err) => panic!("Failed to call verify state: {{:?}}", err),
+            }}
+        }}
+        Err(err) if err.is_out_of_fuel() => WasmOutcome::OutOfFuel,
+        Err(err) => panic!("Failed to start: {{:?}}", err),
+    }};
 
     env::log(&format!(
-        "Compile WASM {{}} - {{:?}}- {{}}",
-        wasm_name, wasm_params, res
+        "Compile WASM {{}} - {{:?}} - {{:?}}",
+        WASM_NAME, wasm_params, result
     ));
 
-    env::commit(&res);
+    // Bind the result to the module and the call, not just to this guest
+    env::commit(&WasmJournal {{
+        wasm_sha256: *Impl::hash_bytes(&wasm_file),
+        method: WASM_NAME.to_string(),
+        arguments: {},
+        committed: store.data().committed.clone(),
+        result,
+    }});
 }}"#
-      };
-  }
+            )
+        };
+    }
+    macro_rules! WASM_BUILD_TEMPLATE_UNIVERSAL_GUEST_MAIN_RS {
+        () => {
+            concat!(
+                r#"#![no_main]
+
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::{{Digest, Impl, Sha256}};
+use serde::{{Deserialize, Serialize}};
+use wasmi::core::{{Trap, TrapCode, F32, F64}};
+use wasmi::{{
+    Caller, Config, Engine, Error, Extern, Func, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, Value,
+}};
+
+risc0_zkvm::guest::entry!(main);
+
+// Limits are part of the guest, and so of its image ID
+const FUEL_LIMIT: Option<u64> = {};
+const MAX_MEMORY_BYTES: Option<usize> = {};
+
+// Host functions the module may import from `zkvm`, as declared in the manifest
+const HOST_IMPORTS: &[&str] = &[{}];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+enum WasmValue {{
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}}
+
+impl WasmValue {{
+    fn to_value(self) -> Value {{
+        match self {{
+            WasmValue::I32(value) => Value::I32(value),
+            WasmValue::I64(value) => Value::I64(value),
+            WasmValue::F32(value) => Value::F32(F32::from_float(value)),
+            WasmValue::F64(value) => Value::F64(F64::from_float(value)),
+        }}
+    }}
+
+    fn from_value(value: &Value) -> Result<Self, String> {{
+        match value {{
+            Value::I32(value) => Ok(WasmValue::I32(*value)),
+            Value::I64(value) => Ok(WasmValue::I64(*value)),
+            Value::F32(value) => Ok(WasmValue::F32(value.to_float())),
+            Value::F64(value) => Ok(WasmValue::F64(value.to_float())),
+            value => Err(format!("Unsupported result type {{:?}}.", value.ty())),
+        }}
+    }}
+}}
+
+"#,
+                $crate::templates::templates::WASM_GUEST_PRELUDE!(),
+                r#"pub fn main() {{
+    // Load environment variables
+    let wasm_file: Vec<u8> = env::read();
+    let method: String = env::read();
+    let arguments: Vec<WasmValue> = env::read();
+    let private_input: Vec<u8> = if HOST_IMPORTS.contains(&"input") {{
+        env::read()
+    }} else {{
+        Vec::new()
+    }};
+
+    let engine = new_engine();
+    let module = Module::new(&engine, &mut &wasm_file[..]).expect("Failed to create module");
+
+    let mut linker = <Linker<HostState>>::new(&engine);
+    let mut store = new_store(&engine, private_input);
+    define_host_imports(&mut linker, &mut store);
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .expect("Failed to instansitate.")
+        .start(&mut store);
+
+    // Running out of fuel is committed as an outcome, any other failure aborts the proof
+    let result = match instance {{
+        Ok(instance) => {{
+            let wasm_fn = instance
+                .get_func(&store, &method)
+                .expect("Failed to get func.");
+
+            // The method is only known at runtime, so are the number and types of its results
+            let params: Vec<Value> = arguments.iter().map(|argument| argument.to_value()).collect();
+            let mut results: Vec<Value> = wasm_fn
+                .ty(&store)
+                .results()
+                .iter()
+                .map(|ty| Value::default(*ty))
+                .collect();
+            match wasm_fn.call(&mut store, &params, &mut results) {{
+                Ok(()) => match results.as_slice() {{
+                    [result] => match WasmValue::from_value(result) {{
+                        Ok(result) => WasmOutcome::Returned(result),
+                        Err(reason) => WasmOutcome::Unsupported(reason),
+                    }},
+                    _ => WasmOutcome::Unsupported(format!(
+                        "{{}} returns {{}} values, expected exactly one.",
+                        method,
+                        results.len()
+                    )),
+                }},
+                Err(err) if err.is_out_of_fuel() => WasmOutcome::OutOfFuel,
+                Err(err) => panic!("Failed to call verify state: {{:?}}", err),
+            }}
+        }}
+        Err(err) if err.is_out_of_fuel() => WasmOutcome::OutOfFuel,
+        Err(err) => panic!("Failed to start: {{:?}}", err),
+    }};
+
+    env::log(&format!("Compile WASM {{}} - {{:?}} - {{:?}}", method, arguments, result));
+
+    env::commit(&WasmJournal {{
+        wasm_sha256: *Impl::hash_bytes(&wasm_file),
+        method,
+        arguments,
+        committed: store.data().committed.clone(),
+        result,
+    }});
+}}"#
+            )
+        };
+    }
 
     pub(crate) use WASM_BUILD_TEMPLATE_BUILD_RS;
     pub(crate) use WASM_BUILD_TEMPLATE_CARGO_TOML;
     pub(crate) use WASM_BUILD_TEMPLATE_GUEST_CARGO_TOML;
     pub(crate) use WASM_BUILD_TEMPLATE_GUEST_MAIN_RS;
     pub(crate) use WASM_BUILD_TEMPLATE_LIB_RS;
+    pub(crate) use WASM_BUILD_TEMPLATE_UNIVERSAL_GUEST_MAIN_RS;
+    pub(crate) use WASM_GUEST_PRELUDE;
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmi::core::TrapCode;
+    use wasmi::{Config, Engine, Linker, Module, Store};
+
+    // The guests tell running out of fuel apart from other failures by its trap code,
+    // with fuel metered the way `new_engine` and `new_store` set it up
+    #[test]
+    fn test_infinite_loop_traps_out_of_fuel() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (func (export "zkmain") (param i32) (result i32) (loop (br 0)) i32.const 0))"#,
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &mut &wasm[..]).unwrap();
+        let mut store = Store::new(&engine, ());
+        store.add_fuel(10_000).unwrap();
+        let instance = <Linker<()>>::new(&engine)
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+
+        let trap = instance
+            .get_typed_func::<i32, i32>(&store, "zkmain")
+            .unwrap()
+            .call(&mut store, 0)
+            .unwrap_err();
+
+        assert!(matches!(trap.trap_code(), Some(TrapCode::OutOfFuel)));
+    }
 }
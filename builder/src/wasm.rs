@@ -0,0 +1,463 @@
+use std::{error::Error, fmt};
+
+use wasmi::{core::ValueType, Engine, ExternType, Module};
+use wasmparser::{Parser, Payload, Validator, WasmFeatures};
+
+use crate::host::{find_host_function, HostImport, HOST_MODULE};
+use crate::DynType;
+
+/// Parameter and result types of an exported WASM function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmSignature {
+    pub argument_type: Vec<DynType>,
+    pub result_type: DynType,
+}
+
+impl ToString for WasmSignature {
+    fn to_string(&self) -> String {
+        let arguments: Vec<String> = self.argument_type.iter().map(|t| t.to_string()).collect();
+        format!(
+            "({}) -> {}",
+            arguments.join(", "),
+            self.result_type.to_string()
+        )
+    }
+}
+
+fn dyn_type(value_type: &ValueType) -> Result<DynType, Box<dyn Error>> {
+    match value_type {
+        ValueType::I32 => Ok(DynType::I32),
+        ValueType::I64 => Ok(DynType::I64),
+        ValueType::F32 => Ok(DynType::F32),
+        ValueType::F64 => Ok(DynType::F64),
+        other => Err(format!("Unsupported WASM value type {:?}.", other).into()),
+    }
+}
+
+/// Reads the signature of the function exported as `method`, parsing the module
+/// with the same `wasmi` version the guest runs it with.
+pub fn read_export_signature(wasm: &[u8], method: &str) -> Result<WasmSignature, Box<dyn Error>> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, &mut &wasm[..])?;
+
+    let export = module
+        .exports()
+        .find(|export| export.name() == method)
+        .ok_or_else(|| format!("WASM module does not export `{}`.", method))?;
+    let func_type = match export.ty() {
+        ExternType::Func(func_type) => func_type,
+        _ => return Err(format!("WASM export `{}` is not a function.", method).into()),
+    };
+
+    let argument_type = func_type
+        .params()
+        .iter()
+        .map(dyn_type)
+        .collect::<Result<Vec<DynType>, _>>()?;
+    if argument_type.is_empty() {
+        return Err(format!("`{}` must take at least one argument.", method).into());
+    }
+
+    let result_type = match func_type.results() {
+        [result] => dyn_type(result)?,
+        results => {
+            return Err(format!(
+                "`{}` must return exactly one value, it returns {}.",
+                method,
+                results.len()
+            )
+            .into())
+        }
+    };
+
+    Ok(WasmSignature {
+        argument_type,
+        result_type,
+    })
+}
+
+/// Uses the signature read from the module, failing if explicitly given types disagree with it.
+pub fn resolve_signature(
+    signature: WasmSignature,
+    argument_type: &Vec<DynType>,
+    result_type: Option<&DynType>,
+) -> Result<WasmSignature, Box<dyn Error>> {
+    // `integer` and `float` are aliases, so compare the names the guest is generated with
+    let same_types = |a: &DynType, b: &DynType| a.to_string() == b.to_string();
+
+    let arguments_match = argument_type.is_empty()
+        || (argument_type.len() == signature.argument_type.len()
+            && argument_type
+                .iter()
+                .zip(&signature.argument_type)
+                .all(|(a, b)| same_types(a, b)));
+    let result_matches = result_type.map_or(true, |r| same_types(r, &signature.result_type));
+
+    if !arguments_match || !result_matches {
+        let expected = WasmSignature {
+            argument_type: if argument_type.is_empty() {
+                signature.argument_type.clone()
+            } else {
+                argument_type.clone()
+            },
+            result_type: *result_type.unwrap_or(&signature.result_type),
+        };
+        return Err(format!(
+            "Given types {} do not match the exported signature {}.",
+            expected.to_string(),
+            signature.to_string()
+        )
+        .into());
+    }
+
+    Ok(signature)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while validating a WASM module before building its guest.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    fn error(message: String, help: Option<&str>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            help: help.map(String::from),
+        }
+    }
+
+    fn warning(message: String, help: Option<&str>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+            help: help.map(String::from),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message)?,
+            Severity::Warning => write!(f, "warning: {}", self.message)?,
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n  help: {}", help)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Proposals the guest's `wasmi` 0.31 engine accepts, it runs with the default `Config`.
+fn guest_features() -> WasmFeatures {
+    WasmFeatures {
+        mutable_global: true,
+        saturating_float_to_int: true,
+        sign_extension: true,
+        reference_types: true,
+        multi_value: true,
+        bulk_memory: true,
+        floats: true,
+        simd: false,
+        relaxed_simd: false,
+        threads: false,
+        tail_call: false,
+        multi_memory: false,
+        exceptions: false,
+        memory64: false,
+        extended_const: false,
+        component_model: false,
+        memory_control: false,
+        // Proposals newer than wasmi 0.31 are off by default
+        ..WasmFeatures::default()
+    }
+}
+
+/// Proposals the guest rejects, with the flag enabling each and a hint.
+const UNSUPPORTED_FEATURES: &[(&str, fn(&mut WasmFeatures) -> &mut bool, &str)] = &[
+    (
+        "SIMD",
+        |features| &mut features.simd,
+        "wasmi 0.31 has no SIMD support, build the module without `+simd128`",
+    ),
+    (
+        "relaxed SIMD",
+        |features| &mut features.relaxed_simd,
+        "wasmi 0.31 has no SIMD support, build the module without `+relaxed-simd`",
+    ),
+    (
+        "threads",
+        |features| &mut features.threads,
+        "wasmi 0.31 has no threads support, build the module without `+atomics` or shared memory",
+    ),
+    (
+        "tail calls",
+        |features| &mut features.tail_call,
+        "tail calls are disabled in the guest, build the module without `+tail-call`",
+    ),
+    (
+        "extended constant expressions",
+        |features| &mut features.extended_const,
+        "extended constant expressions are disabled in the guest",
+    ),
+    (
+        "exceptions",
+        |features| &mut features.exceptions,
+        "wasmi 0.31 has no exception handling support",
+    ),
+    (
+        "memory64",
+        |features| &mut features.memory64,
+        "wasmi 0.31 only supports 32-bit memories",
+    ),
+    (
+        "multi-memory",
+        |features| &mut features.multi_memory,
+        "wasmi 0.31 supports a single memory per module",
+    ),
+];
+
+fn validates_with(wasm: &[u8], features: WasmFeatures) -> Result<(), String> {
+    Validator::new_with_features(features)
+        .validate_all(wasm)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Validates the module against the proposals the guest supports. A module that only
+/// validates with unsupported proposals gets one error per proposal it needs, found by
+/// disabling them one at a time.
+fn validate_features(wasm: &[u8]) -> Vec<Diagnostic> {
+    if validates_with(wasm, guest_features()).is_ok() {
+        return Vec::new();
+    }
+
+    let mut all_features = guest_features();
+    for (_, flag, _) in UNSUPPORTED_FEATURES {
+        *flag(&mut all_features) = true;
+    }
+    if let Err(message) = validates_with(wasm, all_features) {
+        return vec![Diagnostic::error(
+            format!("WASM module is invalid: {}", message),
+            None,
+        )];
+    }
+
+    UNSUPPORTED_FEATURES
+        .iter()
+        .filter_map(|(name, flag, help)| {
+            let mut features = all_features;
+            *flag(&mut features) = false;
+            validates_with(wasm, features).err().map(|message| {
+                Diagnostic::error(
+                    format!(
+                        "WASM module uses {}, which the guest does not support: {}",
+                        name, message
+                    ),
+                    Some(help),
+                )
+            })
+        })
+        .collect()
+}
+
+fn start_function(wasm: &[u8]) -> Option<u32> {
+    Parser::new(0)
+        .parse_all(wasm)
+        .find_map(|payload| match payload {
+            Ok(Payload::StartSection { func, .. }) => Some(func),
+            _ => None,
+        })
+}
+
+/// Checks a module the way the guest will load it, without building anything.
+/// Returns every finding; the module is only usable if none of them is an error.
+pub fn validate_module(wasm: &[u8], method: &str, host_imports: &[HostImport]) -> Vec<Diagnostic> {
+    let feature_diagnostics = validate_features(wasm);
+    if !feature_diagnostics.is_empty() {
+        return feature_diagnostics;
+    }
+
+    let engine = Engine::default();
+    let module = match Module::new(&engine, &mut &wasm[..]) {
+        Ok(module) => module,
+        Err(err) => {
+            return vec![Diagnostic::error(
+                format!("WASM module is invalid or unsupported: {}", err),
+                None,
+            )];
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+
+    // The guest's `Linker` only defines the enabled host functions
+    let mut uses_host_functions = false;
+    for import in module.imports() {
+        if let (HOST_MODULE, ExternType::Func(func_type)) = (import.module(), import.ty()) {
+            if let Some((host_import, (_, params, results))) = find_host_function(import.name()) {
+                uses_host_functions = true;
+                if !host_imports.contains(&host_import) {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        message: format!(
+                            "WASM module imports host function `{}.{}`, which is not enabled",
+                            HOST_MODULE,
+                            import.name()
+                        ),
+                        help: Some(format!("build with `--import {}`", host_import.name())),
+                    });
+                } else if func_type.params() != *params || func_type.results() != *results {
+                    diagnostics.push(Diagnostic::error(
+                        format!(
+                            "WASM module imports `{}.{}` with the wrong signature {:?} -> {:?}",
+                            HOST_MODULE,
+                            import.name(),
+                            func_type.params(),
+                            func_type.results()
+                        ),
+                        Some(
+                            "expected parameters and results are listed in `builder build --help`",
+                        ),
+                    ));
+                }
+                continue;
+            }
+        }
+
+        let kind = match import.ty() {
+            ExternType::Func(_) => "function",
+            ExternType::Global(_) => "global",
+            ExternType::Memory(_) => "memory",
+            ExternType::Table(_) => "table",
+        };
+        diagnostics.push(Diagnostic::error(
+            format!(
+                "WASM module imports {} `{}.{}`, which the guest cannot provide",
+                kind,
+                import.module(),
+                import.name()
+            ),
+            Some("remove the import, e.g. avoid WASI or JS bindings in the module"),
+        ));
+    }
+
+    // Host functions take pointers into the memory the module exports as `memory`
+    let exports_memory = module
+        .exports()
+        .any(|export| export.name() == "memory" && matches!(export.ty(), ExternType::Memory(_)));
+    if uses_host_functions && !exports_memory {
+        diagnostics.push(Diagnostic::error(
+            "WASM module uses host functions but does not export its memory as `memory`".into(),
+            None,
+        ));
+    }
+
+    if let Some(func) = start_function(wasm) {
+        diagnostics.push(Diagnostic::warning(
+            format!("WASM module has a start function (function {})", func),
+            Some("it runs inside the zkVM on every proof, before the exported method"),
+        ));
+    }
+
+    if let Err(err) = read_export_signature(wasm, method) {
+        diagnostics.push(Diagnostic::error(err.to_string(), None));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(wat: &str) -> Vec<String> {
+        let wasm = wat::parse_str(wat).unwrap();
+        validate_module(&wasm, "zkmain", &[])
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    fn assert_rejected_for(wat: &str, feature: &str) {
+        let messages = rejection(wat);
+        let expected = format!("WASM module uses {},", feature);
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.starts_with(&expected)),
+            "expected a {} error, got {:?}",
+            feature,
+            messages
+        );
+    }
+
+    #[test]
+    fn test_supported_module_is_accepted() {
+        let messages =
+            rejection(r#"(module (func (export "zkmain") (param i32) (result i32) local.get 0))"#);
+        assert!(messages.is_empty(), "{:?}", messages);
+    }
+
+    #[test]
+    fn test_simd_is_rejected() {
+        assert_rejected_for(
+            r#"(module (func (result i32) v128.const i64x2 0 0 i32x4.extract_lane 0))"#,
+            "SIMD",
+        );
+    }
+
+    #[test]
+    fn test_relaxed_simd_is_rejected() {
+        assert_rejected_for(
+            r#"(module (func (param v128 v128) (result v128)
+                local.get 0 local.get 1 i8x16.relaxed_swizzle))"#,
+            "relaxed SIMD",
+        );
+    }
+
+    #[test]
+    fn test_threads_are_rejected() {
+        assert_rejected_for(r#"(module (memory 1 1 shared))"#, "threads");
+    }
+
+    #[test]
+    fn test_tail_calls_are_rejected() {
+        assert_rejected_for(r#"(module (func $f return_call $f))"#, "tail calls");
+    }
+
+    #[test]
+    fn test_extended_const_is_rejected() {
+        assert_rejected_for(
+            r#"(module (global i32 (i32.add (i32.const 1) (i32.const 2))))"#,
+            "extended constant expressions",
+        );
+    }
+
+    #[test]
+    fn test_exceptions_are_rejected() {
+        assert_rejected_for(r#"(module (tag))"#, "exceptions");
+    }
+
+    #[test]
+    fn test_memory64_is_rejected() {
+        assert_rejected_for(r#"(module (memory i64 1))"#, "memory64");
+    }
+
+    #[test]
+    fn test_multi_memory_is_rejected() {
+        assert_rejected_for(r#"(module (memory 1) (memory 1))"#, "multi-memory");
+    }
+}
@@ -0,0 +1,21 @@
+import { NextResponse } from 'next/server'
+import { API_SERVER } from '@/constants/api'
+
+// Proof creation is proxied so the API key only ever lives on the server.
+// `API_KEY` must not be prefixed with `NEXT_PUBLIC_`, or Next.js inlines it into the browser bundle.
+export async function POST(request: Request) {
+	const res = await fetch(API_SERVER('proofs'), {
+		method: 'POST',
+		headers: {
+			'Content-Type': 'application/json',
+			...(process.env.API_KEY && { 'x-api-key': process.env.API_KEY })
+		},
+		body: await request.text(),
+		cache: 'no-store'
+	})
+
+	return new NextResponse(await res.text(), {
+		status: res.status,
+		headers: { 'Content-Type': res.headers.get('Content-Type') ?? 'text/plain' }
+	})
+}
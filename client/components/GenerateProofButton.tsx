@@ -8,7 +8,6 @@ import { Loader2 } from 'lucide-react'
 import { useRouter } from 'next/navigation'
 import { useState } from 'react'
 import { toast } from './ui/use-toast'
-import { API_SERVER } from '@/constants/api'
 
 export default function GenerateProofButton({
 	imageId,
@@ -45,7 +44,8 @@ export default function GenerateProofButton({
 		}
 
 		try {
-			const res = await fetch(API_SERVER('proofs'), {
+			// Sent through the app's own route, which adds the API key server-side
+			const res = await fetch('/api/proofs', {
 				method: 'POST',
 				headers: { 'Content-Type': 'application/json' },
 				body: JSON.stringify(data)
@@ -0,0 +1,37 @@
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    // Expose the risc0-zkvm version this crate is built against, reported by `/version`.
+    // It is read from the lockfile cargo resolved for this build, which lives in the
+    // workspace root.
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let lockfile = manifest_dir
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.exists());
+
+    let mut risc0_zkvm_version = None;
+    if let Some(lockfile) = lockfile {
+        println!("cargo:rerun-if-changed={}", lockfile.display());
+        let lock = fs::read_to_string(&lockfile).unwrap_or_default();
+        risc0_zkvm_version = locked_version(&lock, "risc0-zkvm");
+    }
+
+    println!(
+        "cargo:rustc-env=RISC0_ZKVM_VERSION={}",
+        risc0_zkvm_version.as_deref().unwrap_or("unknown")
+    );
+}
+
+/// Version of `name` in a `Cargo.lock`, whose packages are `[[package]]` tables starting
+/// with their `name` and `version`.
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let name_line = format!("name = \"{}\"", name);
+
+    lock.split("[[package]]")
+        .find(|package| package.lines().any(|line| line.trim() == name_line))?
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("version = \""))
+        .and_then(|rest| rest.strip_suffix('"'))
+        .map(|version| version.to_string())
+}
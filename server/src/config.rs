@@ -0,0 +1,786 @@
+use std::{env, fmt, fs, io, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::logging::LogFormat;
+
+/// Path of the TOML config file, `config.toml` in the working directory by default.
+const CONFIG_PATH_ENV: &str = "ZK_EXPLORER_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => {
+                write!(f, "failed to read config file {}: {}", path.display(), err)
+            }
+            ConfigError::Parse(path, err) => {
+                write!(f, "failed to parse config file {}: {}", path.display(), err)
+            }
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:\n  - {}", problems.join("\n  - "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub web: WebConfig,
+    pub db: DbConfig,
+    pub storage: StorageConfig,
+    pub prover: ProverConfig,
+    pub limits: LimitsConfig,
+    pub retention: RetentionConfig,
+    pub bundles: BundleConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    /// Socket address the HTTP server binds to. Env: `WEB_HOST`.
+    pub host: String,
+    /// Env: `LOG_FORMAT`.
+    pub log_format: LogFormat,
+    /// How long running proofs may take to finish on shutdown. Env: `SHUTDOWN_GRACE_PERIOD_SECS`.
+    pub shutdown_grace_period_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbEngine {
+    /// SurrealDB server reached over WebSocket.
+    #[default]
+    Remote,
+    /// Embedded, non-persistent database; every start is a fresh one.
+    Memory,
+    /// Embedded database stored at `db.path`, needs the `rocksdb` feature.
+    RocksDb,
+    /// Embedded database stored at `db.path`, needs the `speedb` feature.
+    SpeeDb,
+}
+
+impl DbEngine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DbEngine::Remote => "remote",
+            DbEngine::Memory => "memory",
+            DbEngine::RocksDb => "rocksdb",
+            DbEngine::SpeeDb => "speedb",
+        }
+    }
+
+    fn is_compiled_in(&self) -> bool {
+        match self {
+            DbEngine::Remote | DbEngine::Memory => true,
+            DbEngine::RocksDb => cfg!(feature = "rocksdb"),
+            DbEngine::SpeeDb => cfg!(feature = "speedb"),
+        }
+    }
+}
+
+impl FromStr for DbEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remote" => Ok(DbEngine::Remote),
+            "memory" => Ok(DbEngine::Memory),
+            "rocksdb" => Ok(DbEngine::RocksDb),
+            "speedb" => Ok(DbEngine::SpeeDb),
+            _ => Err(format!(
+                "Unknown database engine `{}`, expected `remote`, `memory`, `rocksdb` or `speedb`.",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbConfig {
+    /// Env: `DB_ENGINE`.
+    pub engine: DbEngine,
+    /// Address of the SurrealDB server, only used by the `remote` engine. Env: `DB_URI`.
+    pub uri: String,
+    /// Data directory of the `rocksdb` and `speedb` engines. Env: `DB_PATH`.
+    pub path: String,
+    /// Only used by the `remote` engine. Env: `DB_USERNAME`.
+    pub username: String,
+    /// Only used by the `remote` engine. Env: `DB_PASSWORD`.
+    pub password: String,
+    /// Used as both the namespace and the database name. Env: `DB_NAMESPACE`.
+    pub namespace: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Web3Storage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Env: `WEB3_STORAGE_TOKEN`.
+    pub web3_storage_token: Option<String>,
+    /// Env: `WEB3_STORAGE_UPLOAD_URL`.
+    pub upload_url: String,
+    /// Uploads are removed with `DELETE <delete_url>/<cid>`; unset if the backend cannot
+    /// delete. Env: `WEB3_STORAGE_DELETE_URL`.
+    pub delete_url: Option<String>,
+    /// IPFS HTTP API used to list and download packages. Env: `IPFS_API_URL`.
+    pub ipfs_api_url: String,
+    /// Env: `STORAGE_REQUEST_TIMEOUT_SECS`.
+    pub request_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProverConfig {
+    /// Sessions still proving after this long are marked `TimedOut`. The prover stops at its
+    /// next check, before executing or proving, but a proof already underway runs to the end
+    /// on its thread and is discarded. Env: `PROVER_TIMEOUT_SECS`.
+    pub timeout_secs: u64,
+    /// Proofs running at once, further sessions wait in `Preparing`.
+    /// Env: `PROVER_MAX_CONCURRENT_PROOFS`.
+    pub max_concurrent_proofs: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_batch_size: usize,
+    pub default_page_limit: usize,
+    pub max_page_limit: usize,
+    /// Largest session bundle accepted by the import endpoint.
+    pub max_bundle_size_bytes: usize,
+    /// Largest total size of the files in an imported bundle once decompressed.
+    pub max_bundle_unpacked_bytes: u64,
+    pub rate_limit: RateLimitConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Env: `RATE_LIMIT_IP_BURST`.
+    pub ip_burst: u32,
+    /// Env: `RATE_LIMIT_IP_PER_SECOND`.
+    pub ip_per_second: f64,
+    /// Env: `RATE_LIMIT_API_KEY_BURST`.
+    pub api_key_burst: u32,
+    /// Env: `RATE_LIMIT_API_KEY_PER_SECOND`.
+    pub api_key_per_second: f64,
+    /// Use the first `X-Forwarded-For` entry as client IP. Env: `RATE_LIMIT_TRUST_FORWARDED_FOR`.
+    pub trust_forwarded_for: bool,
+}
+
+/// Finished sessions that are not pinned are collected once they are older than
+/// `max_age_days`, or once their image has more than `max_sessions_per_image` newer ones.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Run the collector in the background. Env: `RETENTION_ENABLED`.
+    pub enabled: bool,
+    /// 0 keeps sessions regardless of age. Env: `RETENTION_MAX_AGE_DAYS`.
+    pub max_age_days: u64,
+    /// 0 keeps any number of sessions per image. Env: `RETENTION_MAX_SESSIONS_PER_IMAGE`.
+    pub max_sessions_per_image: usize,
+    /// Env: `RETENTION_INTERVAL_SECS`.
+    pub interval_secs: u64,
+    /// Also delete receipts of collected sessions. Env: `RETENTION_DELETE_RECEIPTS`.
+    pub delete_receipts: bool,
+}
+
+/// Bundles are signed with Ed25519 over their index, and only imported when signed by a
+/// trusted key.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BundleConfig {
+    /// Hex encoded 32 byte seed exported bundles are signed with, export is disabled without
+    /// it. Env: `BUNDLE_SIGNING_KEY`.
+    pub signing_key: Option<String>,
+    /// Hex encoded public keys whose bundles are imported, besides the one of `signing_key`.
+    /// Env: `BUNDLE_TRUSTED_KEYS`, comma separated.
+    pub trusted_keys: Vec<String>,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            host: "0.0.0.0:3005".into(),
+            log_format: LogFormat::Pretty,
+            shutdown_grace_period_secs: 300,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Web3Storage,
+            web3_storage_token: None,
+            upload_url: "https://api.web3.storage/upload".into(),
+            delete_url: Some("https://api.web3.storage/user/uploads".into()),
+            ipfs_api_url: "https://dweb.link/api/v0".into(),
+            request_timeout_secs: 60,
+        }
+    }
+}
+
+impl Default for ProverConfig {
+    fn default() -> Self {
+        ProverConfig {
+            timeout_secs: 3600,
+            max_concurrent_proofs: 4,
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_batch_size: 1000,
+            default_page_limit: 20,
+            max_page_limit: 100,
+            max_bundle_size_bytes: 64 * 1024 * 1024,
+            max_bundle_unpacked_bytes: 256 * 1024 * 1024,
+            rate_limit: RateLimitConfig::default(),
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            enabled: false,
+            max_age_days: 30,
+            max_sessions_per_image: 0,
+            interval_secs: 3600,
+            delete_receipts: true,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            ip_burst: 60,
+            ip_per_second: 1.0,
+            api_key_burst: 300,
+            api_key_per_second: 5.0,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+fn override_from_env<T: FromStr>(name: &str, target: &mut T, problems: &mut Vec<String>) {
+    if let Ok(value) = env::var(name) {
+        match value.parse() {
+            Ok(value) => *target = value,
+            Err(_) => problems.push(format!(
+                "environment variable {} has an invalid value `{}`",
+                name, value
+            )),
+        }
+    }
+}
+
+fn override_option_from_env(name: &str, target: &mut Option<String>) {
+    if let Ok(value) = env::var(name) {
+        *target = Some(value);
+    }
+}
+
+impl Config {
+    /// Loads the config file (if present), applies environment overrides and validates the result.
+    pub fn load() -> Result<Config, ConfigError> {
+        let path_override = env::var(CONFIG_PATH_ENV).ok();
+        let path = PathBuf::from(path_override.as_deref().unwrap_or(DEFAULT_CONFIG_PATH));
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.clone(), err))?
+            }
+            // The default config file is optional, an explicitly configured one is not
+            Err(err) if err.kind() == io::ErrorKind::NotFound && path_override.is_none() => {
+                Config::default()
+            }
+            Err(err) => return Err(ConfigError::Read(path, err)),
+        };
+
+        let mut problems = Vec::new();
+        config.apply_env_overrides(&mut problems);
+        config.validate(&mut problems);
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    fn apply_env_overrides(&mut self, problems: &mut Vec<String>) {
+        override_from_env("WEB_HOST", &mut self.web.host, problems);
+        override_from_env("LOG_FORMAT", &mut self.web.log_format, problems);
+        override_from_env(
+            "SHUTDOWN_GRACE_PERIOD_SECS",
+            &mut self.web.shutdown_grace_period_secs,
+            problems,
+        );
+
+        override_from_env("DB_ENGINE", &mut self.db.engine, problems);
+        override_from_env("DB_URI", &mut self.db.uri, problems);
+        override_from_env("DB_PATH", &mut self.db.path, problems);
+        override_from_env("DB_USERNAME", &mut self.db.username, problems);
+        override_from_env("DB_PASSWORD", &mut self.db.password, problems);
+        override_from_env("DB_NAMESPACE", &mut self.db.namespace, problems);
+
+        override_option_from_env("WEB3_STORAGE_TOKEN", &mut self.storage.web3_storage_token);
+        override_from_env(
+            "WEB3_STORAGE_UPLOAD_URL",
+            &mut self.storage.upload_url,
+            problems,
+        );
+        override_option_from_env("WEB3_STORAGE_DELETE_URL", &mut self.storage.delete_url);
+        override_from_env("IPFS_API_URL", &mut self.storage.ipfs_api_url, problems);
+        override_from_env(
+            "STORAGE_REQUEST_TIMEOUT_SECS",
+            &mut self.storage.request_timeout_secs,
+            problems,
+        );
+
+        override_from_env(
+            "PROVER_TIMEOUT_SECS",
+            &mut self.prover.timeout_secs,
+            problems,
+        );
+        override_from_env(
+            "PROVER_MAX_CONCURRENT_PROOFS",
+            &mut self.prover.max_concurrent_proofs,
+            problems,
+        );
+
+        let retention = &mut self.retention;
+        override_from_env("RETENTION_ENABLED", &mut retention.enabled, problems);
+        override_from_env(
+            "RETENTION_MAX_AGE_DAYS",
+            &mut retention.max_age_days,
+            problems,
+        );
+        override_from_env(
+            "RETENTION_MAX_SESSIONS_PER_IMAGE",
+            &mut retention.max_sessions_per_image,
+            problems,
+        );
+        override_from_env(
+            "RETENTION_INTERVAL_SECS",
+            &mut retention.interval_secs,
+            problems,
+        );
+        override_from_env(
+            "RETENTION_DELETE_RECEIPTS",
+            &mut retention.delete_receipts,
+            problems,
+        );
+
+        override_option_from_env("BUNDLE_SIGNING_KEY", &mut self.bundles.signing_key);
+        if let Ok(value) = env::var("BUNDLE_TRUSTED_KEYS") {
+            self.bundles.trusted_keys = value
+                .split(',')
+                .map(|key| key.trim().to_string())
+                .filter(|key| !key.is_empty())
+                .collect();
+        }
+
+        let rate_limit = &mut self.limits.rate_limit;
+        override_from_env("RATE_LIMIT_IP_BURST", &mut rate_limit.ip_burst, problems);
+        override_from_env(
+            "RATE_LIMIT_IP_PER_SECOND",
+            &mut rate_limit.ip_per_second,
+            problems,
+        );
+        override_from_env(
+            "RATE_LIMIT_API_KEY_BURST",
+            &mut rate_limit.api_key_burst,
+            problems,
+        );
+        override_from_env(
+            "RATE_LIMIT_API_KEY_PER_SECOND",
+            &mut rate_limit.api_key_per_second,
+            problems,
+        );
+        override_from_env(
+            "RATE_LIMIT_TRUST_FORWARDED_FOR",
+            &mut rate_limit.trust_forwarded_for,
+            problems,
+        );
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.web.host.parse::<SocketAddr>().is_err() {
+            problems.push(format!(
+                "web.host (WEB_HOST) must be a socket address like 0.0.0.0:3005, got `{}`",
+                self.web.host
+            ));
+        }
+
+        let mut required_db_fields = vec![("db.namespace (DB_NAMESPACE)", &self.db.namespace)];
+        match self.db.engine {
+            DbEngine::Remote => required_db_fields.extend([
+                ("db.uri (DB_URI)", &self.db.uri),
+                ("db.username (DB_USERNAME)", &self.db.username),
+                ("db.password (DB_PASSWORD)", &self.db.password),
+            ]),
+            DbEngine::Memory => {}
+            DbEngine::RocksDb | DbEngine::SpeeDb => {
+                required_db_fields.push(("db.path (DB_PATH)", &self.db.path))
+            }
+        }
+        for (name, value) in required_db_fields {
+            if value.is_empty() {
+                problems.push(format!(
+                    "{} is required for the {} engine",
+                    name,
+                    self.db.engine.as_str()
+                ));
+            }
+        }
+        if !self.db.engine.is_compiled_in() {
+            problems.push(format!(
+                "db.engine (DB_ENGINE) `{}` is not compiled in, \
+                    rebuild the server with the `{0}` feature",
+                self.db.engine.as_str()
+            ));
+        }
+
+        match self.storage.backend {
+            StorageBackend::Web3Storage => {
+                if self.storage.web3_storage_token.is_none() {
+                    problems.push(
+                        "storage.web3_storage_token (WEB3_STORAGE_TOKEN) is required \
+                            for the web3storage backend"
+                            .into(),
+                    );
+                }
+            }
+        }
+        let mut storage_urls = vec![
+            ("storage.upload_url", &self.storage.upload_url),
+            ("storage.ipfs_api_url", &self.storage.ipfs_api_url),
+        ];
+        if let Some(delete_url) = &self.storage.delete_url {
+            storage_urls.push(("storage.delete_url", delete_url));
+        }
+        for (name, value) in storage_urls {
+            if !value.starts_with("http://") && !value.starts_with("https://") {
+                problems.push(format!("{} must be an http(s) URL, got `{}`", name, value));
+            }
+        }
+        if self.storage.request_timeout_secs == 0 {
+            problems.push("storage.request_timeout_secs must be greater than 0".into());
+        }
+
+        if self.prover.timeout_secs == 0 {
+            problems.push("prover.timeout_secs must be greater than 0".into());
+        }
+
+        if self.prover.max_concurrent_proofs == 0 {
+            problems.push("prover.max_concurrent_proofs must be greater than 0".into());
+        }
+
+        let limits = &self.limits;
+        if limits.max_batch_size == 0 {
+            problems.push("limits.max_batch_size must be greater than 0".into());
+        }
+        if limits.max_bundle_size_bytes == 0 || limits.max_bundle_unpacked_bytes == 0 {
+            problems.push("limits bundle sizes must be greater than 0".into());
+        }
+        if limits.default_page_limit == 0 || limits.default_page_limit > limits.max_page_limit {
+            problems.push(
+                "limits.default_page_limit must be between 1 and limits.max_page_limit".into(),
+            );
+        }
+        let rate_limit = &limits.rate_limit;
+        if rate_limit.ip_burst == 0 || rate_limit.api_key_burst == 0 {
+            problems.push("limits.rate_limit bursts must be greater than 0".into());
+        }
+        if rate_limit.ip_per_second <= 0.0 || rate_limit.api_key_per_second <= 0.0 {
+            problems.push("limits.rate_limit refill rates must be greater than 0".into());
+        }
+
+        let retention = &self.retention;
+        if retention.enabled && retention.interval_secs == 0 {
+            problems.push("retention.interval_secs must be greater than 0".into());
+        }
+        if retention.enabled && retention.max_age_days == 0 && retention.max_sessions_per_image == 0
+        {
+            problems.push(
+                "retention is enabled but neither retention.max_age_days \
+                    nor retention.max_sessions_per_image is set"
+                    .into(),
+            );
+        }
+
+        let bundles = &self.bundles;
+        let is_32_hex_bytes =
+            |key: &String| matches!(hex::decode(key), Ok(bytes) if bytes.len() == 32);
+        if bundles
+            .signing_key
+            .as_ref()
+            .map_or(false, |key| !is_32_hex_bytes(key))
+        {
+            problems.push("bundles.signing_key (BUNDLE_SIGNING_KEY) must be 32 hex bytes".into());
+        }
+        if !bundles.trusted_keys.iter().all(is_32_hex_bytes) {
+            problems.push(
+                "bundles.trusted_keys (BUNDLE_TRUSTED_KEYS) must be 32 hex bytes each".into(),
+            );
+        }
+    }
+
+    pub fn retention_interval(&self) -> Duration {
+        Duration::from_secs(self.retention.interval_secs)
+    }
+
+    pub fn storage_request_timeout(&self) -> Duration {
+        Duration::from_secs(self.storage.request_timeout_secs)
+    }
+
+    pub fn prover_timeout(&self) -> Duration {
+        Duration::from_secs(self.prover.timeout_secs)
+    }
+
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(self.web.shutdown_grace_period_secs)
+    }
+}
+
+/// Makes the loaded config available through [`config`].
+pub fn init(config: Config) {
+    CONFIG.set(config).expect("Config is already initialized");
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get().expect("Config is not initialized")
+}
+
+/// Initializes the default config if nothing did yet, for tests of code reading [`config`].
+#[cfg(test)]
+pub fn init_test_config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Environment variables are shared by all tests, the ones touching them take turns.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn valid_config() -> Config {
+        let mut config = Config::default();
+        config.db.engine = DbEngine::Memory;
+        config.db.namespace = "test".into();
+        config.storage.web3_storage_token = Some("token".into());
+
+        config
+    }
+
+    fn problems(config: &Config) -> Vec<String> {
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+
+        problems
+    }
+
+    #[test]
+    fn test_valid_config_has_no_problems() {
+        assert!(problems(&valid_config()).is_empty());
+    }
+
+    #[test]
+    fn test_env_overrides_the_config_file() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let mut config: Config = toml::from_str(
+            r#"
+            [web]
+            host = "127.0.0.1:1000"
+
+            [prover]
+            timeout_secs = 10
+            max_concurrent_proofs = 2
+            "#,
+        )
+        .unwrap();
+
+        env::set_var("WEB_HOST", "127.0.0.1:2000");
+        env::set_var("BUNDLE_TRUSTED_KEYS", "aa, bb,");
+        let mut problems = Vec::new();
+        config.apply_env_overrides(&mut problems);
+        env::remove_var("WEB_HOST");
+        env::remove_var("BUNDLE_TRUSTED_KEYS");
+
+        assert!(problems.is_empty());
+        assert_eq!(config.web.host, "127.0.0.1:2000");
+        assert_eq!(config.bundles.trusted_keys, vec!["aa", "bb"]);
+        // Unset variables keep the file's values, and unset keys the defaults
+        assert_eq!(config.prover.timeout_secs, 10);
+        assert_eq!(config.prover.max_concurrent_proofs, 2);
+        assert_eq!(config.limits.max_batch_size, 1000);
+    }
+
+    #[test]
+    fn test_invalid_env_value_is_a_problem() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let mut config = valid_config();
+
+        env::set_var("PROVER_TIMEOUT_SECS", "soon");
+        let mut problems = Vec::new();
+        config.apply_env_overrides(&mut problems);
+        env::remove_var("PROVER_TIMEOUT_SECS");
+
+        assert_eq!(
+            problems,
+            vec!["environment variable PROVER_TIMEOUT_SECS has an invalid value `soon`"]
+        );
+        assert_eq!(config.prover.timeout_secs, 3600);
+    }
+
+    #[test]
+    fn test_load_reads_the_configured_file() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let path = env::temp_dir().join(format!("zk-explorer-config-{}.toml", std::process::id()));
+
+        fs::write(&path, "[db]\nengine = \"memory\"\nnamespace = \"test\"\n").unwrap();
+        env::set_var(CONFIG_PATH_ENV, &path);
+        env::set_var("WEB3_STORAGE_TOKEN", "token");
+        let loaded = Config::load();
+
+        fs::write(&path, "[db\n").unwrap();
+        let unparsable = Config::load();
+
+        fs::remove_file(&path).unwrap();
+        let missing = Config::load();
+        env::remove_var(CONFIG_PATH_ENV);
+        env::remove_var("WEB3_STORAGE_TOKEN");
+
+        assert_eq!(loaded.unwrap().db.namespace, "test");
+        assert!(matches!(unparsable, Err(ConfigError::Parse(..))));
+        // Unlike the default one, an explicitly configured file must exist
+        assert!(matches!(missing, Err(ConfigError::Read(..))));
+    }
+
+    #[test]
+    fn test_each_invalid_setting_is_reported() {
+        // Expected problem and how to invalidate a valid config to get it
+        type Case = (&'static str, fn(&mut Config));
+        let cases: Vec<Case> = vec![
+            ("web.host (WEB_HOST) must be a socket address", |c| {
+                c.web.host = "localhost".into()
+            }),
+            (
+                "db.namespace (DB_NAMESPACE) is required for the memory engine",
+                |c| c.db.namespace.clear(),
+            ),
+            ("db.uri (DB_URI) is required for the remote engine", |c| {
+                c.db.engine = DbEngine::Remote;
+                c.db.username = "root".into();
+                c.db.password = "root".into();
+            }),
+            (
+                "storage.web3_storage_token (WEB3_STORAGE_TOKEN) is required",
+                |c| c.storage.web3_storage_token = None,
+            ),
+            ("storage.delete_url must be an http(s) URL", |c| {
+                c.storage.delete_url = Some("ftp://example.com".into())
+            }),
+            ("storage.request_timeout_secs must be greater than 0", |c| {
+                c.storage.request_timeout_secs = 0
+            }),
+            ("prover.timeout_secs must be greater than 0", |c| {
+                c.prover.timeout_secs = 0
+            }),
+            ("prover.max_concurrent_proofs must be greater than 0", |c| {
+                c.prover.max_concurrent_proofs = 0
+            }),
+            ("limits.max_batch_size must be greater than 0", |c| {
+                c.limits.max_batch_size = 0
+            }),
+            ("limits bundle sizes must be greater than 0", |c| {
+                c.limits.max_bundle_unpacked_bytes = 0
+            }),
+            ("limits.default_page_limit must be between 1", |c| {
+                c.limits.default_page_limit = c.limits.max_page_limit + 1
+            }),
+            ("limits.rate_limit bursts must be greater than 0", |c| {
+                c.limits.rate_limit.ip_burst = 0
+            }),
+            (
+                "limits.rate_limit refill rates must be greater than 0",
+                |c| c.limits.rate_limit.api_key_per_second = 0.0,
+            ),
+            ("retention.interval_secs must be greater than 0", |c| {
+                c.retention.enabled = true;
+                c.retention.interval_secs = 0;
+            }),
+            ("retention is enabled but neither", |c| {
+                c.retention.enabled = true;
+                c.retention.max_age_days = 0;
+            }),
+            (
+                "bundles.signing_key (BUNDLE_SIGNING_KEY) must be 32 hex bytes",
+                |c| c.bundles.signing_key = Some("abcd".into()),
+            ),
+            (
+                "bundles.trusted_keys (BUNDLE_TRUSTED_KEYS) must be 32 hex bytes",
+                |c| c.bundles.trusted_keys = vec![hex::encode([0u8; 32]), "not hex".into()],
+            ),
+        ];
+
+        for (expected, invalidate) in cases {
+            let mut config = valid_config();
+            invalidate(&mut config);
+
+            let problems = problems(&config);
+            assert_eq!(problems.len(), 1, "{}: {:?}", expected, problems);
+            assert!(problems[0].starts_with(expected), "{:?}", problems);
+        }
+    }
+
+    #[test]
+    fn test_engine_without_its_feature_is_reported() {
+        let mut config = valid_config();
+        config.db.engine = DbEngine::RocksDb;
+        config.db.path = "data".into();
+
+        let problems = problems(&config);
+        if cfg!(feature = "rocksdb") {
+            assert!(problems.is_empty());
+        } else {
+            assert_eq!(
+                problems,
+                vec![
+                    "db.engine (DB_ENGINE) `rocksdb` is not compiled in, \
+                        rebuild the server with the `rocksdb` feature"
+                ]
+            );
+        }
+    }
+}
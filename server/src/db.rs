@@ -1,32 +1,127 @@
 use surrealdb::{
-    engine::remote::ws::{Client, Ws},
+    engine::any::{self, Any},
     opt::auth::Root,
     Surreal,
 };
+use tracing::info;
 
-pub static DB: Surreal<Client> = Surreal::init();
+use crate::config::{DbConfig, DbEngine};
 
-pub async fn connect_db(
-    db_uri: &str,
-    db_username: &str,
-    db_password: &str,
-    db_namespace: &str,
-) -> surrealdb::Result<()> {
-    DB.connect::<Ws>(db_uri).await.expect("Failed to connect");
+/// Database handle held by the app state; clones share the same connection.
+pub type Db = Surreal<Any>;
 
-    DB.signin(Root {
-        username: &db_username,
-        password: &db_password,
-    })
-    .await
-    .expect("Failed to signin");
+/// Builds the endpoint understood by `engine::any` for the configured engine.
+fn endpoint(config: &DbConfig) -> String {
+    match config.engine {
+        DbEngine::Remote if config.uri.contains("://") => config.uri.clone(),
+        DbEngine::Remote => format!("ws://{}", config.uri),
+        DbEngine::Memory => "mem://".into(),
+        DbEngine::RocksDb => format!("rocksdb://{}", config.path),
+        DbEngine::SpeeDb => format!("speedb://{}", config.path),
+    }
+}
+
+pub async fn connect_db(config: &DbConfig) -> surrealdb::Result<Db> {
+    let endpoint = endpoint(config);
+    let db = any::connect(endpoint.as_str()).await?;
+
+    // Embedded engines run in-process without users, there is nobody to sign in as
+    if config.engine == DbEngine::Remote {
+        db.signin(Root {
+            username: &config.username,
+            password: &config.password,
+        })
+        .await?;
+    }
+
+    db.use_ns(&config.namespace)
+        .use_db(&config.namespace)
+        .await?;
+
+    info!(engine = config.engine.as_str(), %endpoint, "Database connected");
+
+    Ok(db)
+}
+
+/// Free-form values are stored as JSON text, so the schemafull tables don't have to define
+/// every nested path. Records read back accept either the text or a plain value.
+pub(crate) mod json_text {
+    use serde::{de::DeserializeOwned, de::Error as _, ser::Error as _, Deserialize};
+    use serde::{Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        match value {
+            Some(value) => {
+                let text = serde_json::to_string(value).map_err(S::Error::custom)?;
+                serializer.serialize_some(&text)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: DeserializeOwned,
+        D: Deserializer<'de>,
+    {
+        match Option::<Value>::deserialize(deserializer)? {
+            Some(Value::String(text)) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(D::Error::custom),
+            Some(value) => serde_json::from_value(value)
+                .map(Some)
+                .map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A fresh, migrated `mem://` database. Every call is isolated from all others.
+#[cfg(test)]
+pub(crate) async fn connect_test_db() -> Db {
+    let config = DbConfig {
+        engine: DbEngine::Memory,
+        namespace: "test".into(),
+        ..Default::default()
+    };
+    let db = connect_db(&config).await.unwrap();
+    crate::migrations::run_migrations(&db).await.unwrap();
+
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Marker {
+        name: String,
+    }
 
-    DB.use_ns(db_namespace)
-        .use_db(db_namespace)
-        .await
-        .expect("Failed to set namespace");
+    #[tokio::test]
+    async fn test_databases_are_isolated() {
+        let first = connect_test_db().await;
+        let second = connect_test_db().await;
 
-    println!("Database connected at {}", db_uri);
+        first
+            .query("CREATE marker SET name = 'first'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
 
-    Ok(())
+        let in_first: Vec<Marker> = first.select("marker").await.unwrap();
+        let in_second: Vec<Marker> = second.select("marker").await.unwrap();
+        assert_eq!(in_first.len(), 1);
+        assert_eq!(in_first[0].name, "first");
+        assert!(in_second.is_empty());
+    }
 }
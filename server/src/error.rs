@@ -9,6 +9,9 @@ pub enum Error {
     FailedToUpdate,
     FailedToVerify,
     LoginFail,
+    Forbidden,
+    QuotaExceeded,
+    ShuttingDown,
 }
 
 impl IntoResponse for Error {
@@ -16,6 +19,14 @@ impl IntoResponse for Error {
         match self {
             Error::NotFound => (StatusCode::NOT_FOUND, "Not Found").into_response(),
             Error::FailedToCreate => (StatusCode::BAD_REQUEST, "Failed to Create").into_response(),
+            Error::LoginFail => (StatusCode::UNAUTHORIZED, "Invalid API Key").into_response(),
+            Error::Forbidden => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
+            Error::QuotaExceeded => {
+                (StatusCode::TOO_MANY_REQUESTS, "API Key Quota Exceeded").into_response()
+            }
+            Error::ShuttingDown => {
+                (StatusCode::SERVICE_UNAVAILABLE, "Server Shutting Down").into_response()
+            }
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "Unhandled Client Error").into_response(),
         }
     }
@@ -0,0 +1,42 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_LOG_FILTER: &str = "warn,server=info,tower_http=debug";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "Unknown log format `{}`, expected `pretty` or `json`.",
+                s
+            )),
+        }
+    }
+}
+
+/// Installs the global tracing subscriber. The filter follows `RUST_LOG` syntax and
+/// falls back to `info` for this crate, `debug` for HTTP request traces and `warn` for
+/// every other dependency when `RUST_LOG` is not set.
+pub fn init(format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Pretty => subscriber.pretty().init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
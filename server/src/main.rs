@@ -1,13 +1,32 @@
-use crate::db::connect_db;
+use crate::{
+    config::{config, Config},
+    db::connect_db,
+};
 
 pub use self::error::{AxumResult, Error};
-use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
-use std::env;
-use tower_http::cors::CorsLayer;
+use axum::{http::StatusCode, middleware, response::IntoResponse, routing::get, Router};
+use services::{
+    retention,
+    session_repository::{SharedSessionRepository, SurrealSessionRepository},
+    shutdown,
+};
+use state::AppState;
+use std::{net::SocketAddr, sync::Arc};
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use tracing::{info, warn};
+use web::{
+    mw_metrics::mw_track_http_metrics,
+    mw_rate_limit::{mw_rate_limit, RateLimiter},
+};
 
+mod config;
 mod db;
 mod error;
+mod logging;
+mod metrics;
+mod migrations;
 mod services;
+mod state;
 mod utils;
 mod web;
 
@@ -21,27 +40,79 @@ async fn api_handler_404() -> impl IntoResponse {
 
 #[tokio::main]
 async fn main() {
-    // Connect DB
-    let db_uri = env::var("DB_URI").unwrap();
-    let db_username = env::var("DB_USERNAME").unwrap();
-    let db_password = env::var("DB_PASSWORD").unwrap();
-    let db_namespace = env::var("DB_NAMESPACE").unwrap();
-    let web_host = env::var("WEB_HOST").unwrap();
+    // Load config
+    let loaded_config = Config::load().unwrap_or_else(|err| {
+        eprintln!("Failed to load config: {}", err);
+        std::process::exit(1);
+    });
+    config::init(loaded_config);
+    let config = config();
+
+    // Setup logging
+    logging::init(config.web.log_format);
 
-    connect_db(&db_uri, &db_username, &db_password, &db_namespace)
+    // Connect DB
+    let db = connect_db(&config.db)
+        .await
+        .expect("Failed to connect to the database");
+    migrations::run_migrations(&db)
         .await
         .expect("Had some errors running migrations :(");
 
+    // Setup metrics
+    metrics::register();
+
+    // Setup session persistence
+    let sessions: SharedSessionRepository = Arc::new(SurrealSessionRepository::new(db.clone()));
+    let state = AppState {
+        db,
+        sessions: sessions.clone(),
+    };
+
+    // Setup proving
+    match web::routes_health::check_prover().await {
+        Some(prover) => info!(%prover, "Prover selected"),
+        None => warn!("No prover available, proofs will fail"),
+    }
+
+    // Setup retention
+    if config.retention.enabled {
+        tokio::spawn(retention::run_periodically(sessions.clone()));
+    }
+
     // Setup routes
-    let router = Router::new()
+    let rate_limiter = RateLimiter::from_config(&config.limits.rate_limit, state.db.clone());
+    let api = Router::new()
         .route("/", get(api_handler_intro))
-        .merge(web::routes_proofs::routes())
+        .merge(web::routes_api_keys::routes(state.clone()))
+        .merge(web::routes_proofs::routes(state.clone()))
+        .merge(web::routes_batches::routes(state.clone()))
+        .merge(web::routes_retention::routes(state.clone()))
+        .merge(web::routes_search::routes(state.clone()))
+        .layer(middleware::from_fn_with_state(rate_limiter, mw_rate_limit));
+    // Probes and scrapers are never rate limited
+    let router = Router::new()
+        .merge(api)
+        .merge(web::routes_metrics::routes())
+        .merge(web::routes_health::routes(state))
+        .route_layer(middleware::from_fn(mw_track_http_metrics))
         .fallback(api_handler_404)
+        .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
-    println!("Server running on port 3005");
-    axum::Server::bind(&web_host.parse().unwrap())
-        .serve(router.into_make_service())
+    info!("Server running on {}", config.web.host);
+    axum::Server::bind(&config.web.host.parse().unwrap())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async {
+            // Keep serving reads while running proofs drain
+            shutdown::wait_for_signal().await;
+            let cancelled =
+                shutdown::drain_sessions(&sessions, config.shutdown_grace_period()).await;
+            info!(cancelled, "Proof sessions drained, shutting down");
+        })
         .await
         .unwrap();
+
+    // Cancelled proofs may still be running on a worker thread, don't wait for them
+    std::process::exit(0);
 }
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Encoder, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+use crate::services::proof_session::ProofSessionStatus;
+
+pub static SESSIONS_CREATED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "zk_sessions_created_total",
+        "Number of proof sessions created.",
+        &["image_cid"]
+    )
+    .unwrap()
+});
+
+pub static SESSIONS_FINISHED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "zk_sessions_finished_total",
+        "Number of proof sessions that reached a final status.",
+        &["image_cid", "status"]
+    )
+    .unwrap()
+});
+
+pub static SESSIONS_COLLECTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "zk_sessions_collected_total",
+        "Number of proof sessions deleted by the retention policy."
+    )
+    .unwrap()
+});
+
+pub static RECEIPTS_DELETED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "zk_receipts_deleted_total",
+        "Number of receipts deleted from storage by the retention policy."
+    )
+    .unwrap()
+});
+
+pub static SESSIONS_QUEUED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "zk_sessions_queue_depth",
+        "Number of proof sessions waiting for or running on the prover."
+    )
+    .unwrap()
+});
+
+pub static PROVING_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "zk_proving_duration_seconds",
+        "Time spent proving a session, including downloads and uploads.",
+        &["status"],
+        exponential_buckets(1.0, 2.0, 14).unwrap()
+    )
+    .unwrap()
+});
+
+/// Segments are proven at their padded size, so this tracks proving cost rather than
+/// the cycles the guest actually executed.
+pub static PROOF_PADDED_CYCLES: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "zk_proof_padded_cycles",
+        "Number of zkVM cycles proven for a session, the sum of its padded segment sizes.",
+        exponential_buckets(65536.0, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+pub static IPFS_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "zk_ipfs_request_duration_seconds",
+        "Latency of IPFS requests.",
+        &["operation"]
+    )
+    .unwrap()
+});
+
+pub static IPFS_REQUEST_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "zk_ipfs_request_errors_total",
+        "Number of failed IPFS requests.",
+        &["operation"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Latency of HTTP requests per route.",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+pub fn status_label(status: &ProofSessionStatus) -> &'static str {
+    match status {
+        ProofSessionStatus::Preparing => "preparing",
+        ProofSessionStatus::InProgress => "in_progress",
+        ProofSessionStatus::Completed => "completed",
+        ProofSessionStatus::TimedOut => "timed_out",
+        ProofSessionStatus::Failed => "failed",
+        ProofSessionStatus::Cancelled => "cancelled",
+    }
+}
+
+pub fn record_session_created(image_cid: &str) {
+    SESSIONS_CREATED.with_label_values(&[image_cid]).inc();
+    SESSIONS_QUEUED.inc();
+}
+
+pub fn record_session_finished(image_cid: &str, status: &ProofSessionStatus, duration: Duration) {
+    let status = status_label(status);
+
+    SESSIONS_FINISHED
+        .with_label_values(&[image_cid, status])
+        .inc();
+    SESSIONS_QUEUED.dec();
+    PROVING_DURATION
+        .with_label_values(&[status])
+        .observe(duration.as_secs_f64());
+}
+
+/// Encodes every registered metric in the Prometheus text format.
+pub fn gather() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("Failed to encode metrics");
+
+    String::from_utf8(buffer).expect("Metrics should be valid UTF-8")
+}
+
+/// Registers every metric up front, so they are exported before their first observation.
+pub fn register() {
+    Lazy::force(&SESSIONS_CREATED);
+    Lazy::force(&SESSIONS_FINISHED);
+    Lazy::force(&SESSIONS_COLLECTED);
+    Lazy::force(&RECEIPTS_DELETED);
+    Lazy::force(&SESSIONS_QUEUED);
+    Lazy::force(&PROVING_DURATION);
+    Lazy::force(&PROOF_PADDED_CYCLES);
+    Lazy::force(&IPFS_REQUEST_DURATION);
+    Lazy::force(&IPFS_REQUEST_ERRORS);
+    Lazy::force(&HTTP_REQUEST_DURATION);
+}
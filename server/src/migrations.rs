@@ -0,0 +1,145 @@
+use serde::Deserialize;
+use tracing::info;
+
+use crate::db::Db;
+
+const MIGRATION: &str = "migration";
+
+/// Bookkeeping table, defined on every start so it exists before the first migration.
+const DEFINE_MIGRATION_TABLE: &str = "
+    DEFINE TABLE migration SCHEMAFULL;
+    DEFINE FIELD version ON migration TYPE int ASSERT $value != NONE;
+    DEFINE FIELD name ON migration TYPE string ASSERT $value != NONE;
+    DEFINE FIELD applied_at ON migration TYPE datetime ASSERT $value != NONE;
+    DEFINE INDEX migration_version ON migration FIELDS version UNIQUE;
+";
+
+struct Migration {
+    version: u32,
+    name: &'static str,
+    statements: &'static str,
+}
+
+/// Applied in order, each one exactly once. Never edit an applied migration, add a new one.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        statements: include_str!("../migrations/0001_initial_schema.surql"),
+    },
+    Migration {
+        version: 2,
+        name: "session_retention",
+        statements: include_str!("../migrations/0002_session_retention.surql"),
+    },
+    Migration {
+        version: 3,
+        name: "batch_creation",
+        statements: include_str!("../migrations/0003_batch_creation.surql"),
+    },
+    Migration {
+        version: 4,
+        name: "api_key_hashes",
+        statements: include_str!("../migrations/0004_api_key_hashes.surql"),
+    },
+    Migration {
+        version: 5,
+        name: "schemafull_tables",
+        statements: include_str!("../migrations/0005_schemafull_tables.surql"),
+    },
+];
+
+#[derive(Debug, Deserialize)]
+struct MigrationRecord {
+    version: u32,
+}
+
+pub async fn current_version(db: &Db) -> surrealdb::Result<u32> {
+    let mut response = db
+        .query("SELECT version FROM type::table($table) ORDER BY version DESC LIMIT 1")
+        .bind(("table", MIGRATION))
+        .await?;
+
+    let latest: Option<MigrationRecord> = response.take(0)?;
+
+    Ok(latest.map_or(0, |record| record.version))
+}
+
+/// Applies every migration newer than the recorded version and returns the resulting version.
+pub async fn run_migrations(db: &Db) -> surrealdb::Result<u32> {
+    db.query(DEFINE_MIGRATION_TABLE).await?.check()?;
+
+    let mut version = current_version(db).await?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        // The statements and the version record are committed together, or not at all
+        db.query("BEGIN TRANSACTION")
+            .query(migration.statements)
+            .query(
+                "CREATE type::thing($table, $version) \
+                    SET version = $version, name = $name, applied_at = time::now()",
+            )
+            .query("COMMIT TRANSACTION")
+            .bind(("table", MIGRATION))
+            .bind(("version", migration.version))
+            .bind(("name", migration.name))
+            .await?
+            .check()?;
+
+        version = migration.version;
+        info!(version, name = migration.name, "Applied database migration");
+    }
+
+    info!(version, "Database schema is up to date");
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::db::connect_test_db;
+
+    /// Every required session field except `session_id`.
+    const SESSION_FIELDS: &str = "is_wasm = true, image_cid = 'bafkreia', status = 'Preparing', \
+        method = 'zkmain', argument_type = ['I32'], arguments = [{ value: '1', arg_type: 'I32' }], \
+        result_type = 'I32', created_at = time::now()";
+
+    #[tokio::test]
+    async fn test_session_with_misspelled_field_is_rejected() {
+        let db = connect_test_db().await;
+
+        let result = db
+            .query(format!(
+                "CREATE session SET sesion_id = 's1', {SESSION_FIELDS}"
+            ))
+            .await
+            .unwrap()
+            .check();
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_unknown_field_is_not_stored() {
+        let db = connect_test_db().await;
+
+        db.query(format!(
+            "CREATE session SET session_id = 's1', unknown_field = 'x', {SESSION_FIELDS}"
+        ))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let mut response = db
+            .query("SELECT * FROM session WHERE session_id = 's1'")
+            .await
+            .unwrap();
+        let stored: Option<Value> = response.take(0).unwrap();
+        let stored = stored.unwrap();
+
+        assert_eq!(stored["method"], "zkmain");
+        assert!(stored.get("unknown_field").is_none());
+    }
+}
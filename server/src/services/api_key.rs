@@ -0,0 +1,244 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use surrealdb::sql::{Datetime, Thing};
+
+use crate::{
+    db::Db,
+    services::{
+        proof_session::{ProofSessionRecord, ProofSessionStatus},
+        session_repository::{API_KEY, SESSION},
+    },
+};
+
+/// Only the SHA-256 of a key is stored, the key itself is returned once by [`create`].
+/// Admin keys create the others through `POST /api/keys`, the first one is provisioned
+/// directly in the `api_key` table, e.g.
+/// `CREATE api_key SET key_hash = crypto::sha256("..."), name = "admin", admin = true,
+/// daily_quota = 1000, max_concurrent_sessions = 10, created_at = time::now()`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKeyRecord {
+    id: Thing,
+    pub key_hash: String,
+    pub name: String,
+    pub daily_quota: usize,
+    pub max_concurrent_sessions: usize,
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub revoked: bool,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewApiKey {
+    pub name: String,
+    pub daily_quota: usize,
+    pub max_concurrent_sessions: usize,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiKeyContent<'a> {
+    key_hash: String,
+    name: &'a String,
+    daily_quota: usize,
+    max_concurrent_sessions: usize,
+    admin: bool,
+    created_at: Datetime,
+}
+
+/// Limits a session insert is checked against, atomically with the insert itself.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionQuota {
+    pub daily_quota: usize,
+    pub max_concurrent_sessions: usize,
+}
+
+/// Hex encoded SHA-256 of a key, as stored in `key_hash`.
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+impl ApiKeyRecord {
+    /// Identifier recorded on sessions, so the secret itself is never persisted elsewhere.
+    pub fn key_id(&self) -> String {
+        self.id.id.to_string()
+    }
+
+    /// Whether this key may change a session: the key that created it, or an admin key.
+    pub fn can_manage(&self, session: &ProofSessionRecord) -> bool {
+        self.admin || session.api_key_id.as_deref() == Some(self.key_id().as_str())
+    }
+
+    pub fn quota(&self) -> SessionQuota {
+        SessionQuota {
+            daily_quota: self.daily_quota,
+            max_concurrent_sessions: self.max_concurrent_sessions,
+        }
+    }
+
+    pub fn allows(&self, usage: &ApiKeyUsage, requested_sessions: usize) -> bool {
+        usage.sessions_today + requested_sessions <= self.daily_quota
+            && usage.active_sessions + requested_sessions <= self.max_concurrent_sessions
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyUsage {
+    pub sessions_today: usize,
+    pub active_sessions: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct CountRecord {
+    count: usize,
+}
+
+/// Stores a new key and returns it with its record. This is the only time the key is known.
+pub async fn create(
+    db: &Db,
+    new_key: &NewApiKey,
+) -> Result<(String, ApiKeyRecord), Box<dyn Error>> {
+    let key = format!("zk_{}", hex::encode(rand::random::<[u8; 32]>()));
+    let record: ApiKeyRecord = db
+        .create(API_KEY)
+        .content(ApiKeyContent {
+            key_hash: hash_key(&key),
+            name: &new_key.name,
+            daily_quota: new_key.daily_quota,
+            max_concurrent_sessions: new_key.max_concurrent_sessions,
+            admin: new_key.admin,
+            created_at: Datetime::default(),
+        })
+        .await?;
+
+    Ok((key, record))
+}
+
+pub async fn fetch_by_key(db: &Db, key: &str) -> Result<ApiKeyRecord, Box<dyn Error>> {
+    let mut response = db
+        .query("SELECT * FROM type::table($table) WHERE key_hash = $key_hash AND revoked != true")
+        .bind(("table", API_KEY))
+        .bind(("key_hash", hash_key(key)))
+        .await?;
+
+    let record: Option<ApiKeyRecord> = response.take(0)?;
+
+    if let Some(record) = record {
+        Ok(record)
+    } else {
+        Err("Error::RowNotFound".into())
+    }
+}
+
+pub async fn usage(db: &Db, api_key: &ApiKeyRecord) -> Result<ApiKeyUsage, Box<dyn Error>> {
+    let mut response = db
+        .query(
+            "SELECT count() FROM type::table($table) \
+                WHERE api_key_id = $api_key_id AND created_at > time::now() - 1d GROUP ALL",
+        )
+        .query(
+            "SELECT count() FROM type::table($table) \
+                WHERE api_key_id = $api_key_id AND status INSIDE $active_statuses GROUP ALL",
+        )
+        .bind(("table", SESSION))
+        .bind(("api_key_id", api_key.key_id()))
+        .bind((
+            "active_statuses",
+            vec![
+                ProofSessionStatus::Preparing,
+                ProofSessionStatus::InProgress,
+            ],
+        ))
+        .await?;
+
+    let sessions_today: Option<CountRecord> = response.take(0)?;
+    let active_sessions: Option<CountRecord> = response.take(1)?;
+
+    Ok(ApiKeyUsage {
+        sessions_today: sessions_today.map_or(0, |c| c.count),
+        active_sessions: active_sessions.map_or(0, |c| c.count),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        db::connect_test_db,
+        services::session_repository::{
+            in_memory::create_test_session_for_key, QuotaExceeded, SharedSessionRepository,
+            SurrealSessionRepository,
+        },
+    };
+
+    fn new_key(max_concurrent_sessions: usize) -> NewApiKey {
+        NewApiKey {
+            name: "ci".into(),
+            daily_quota: 10,
+            max_concurrent_sessions,
+            admin: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_the_key_hash_is_stored() {
+        let db = connect_test_db().await;
+
+        let (key, record) = create(&db, &new_key(1)).await.unwrap();
+
+        assert_ne!(record.key_hash, key);
+        assert_eq!(record.key_hash, hash_key(&key));
+        assert_eq!(
+            fetch_by_key(&db, &key).await.unwrap().key_id(),
+            record.key_id()
+        );
+        assert!(fetch_by_key(&db, &record.key_hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_insert_is_checked_against_the_quota() {
+        let db = connect_test_db().await;
+        let sessions: SharedSessionRepository = Arc::new(SurrealSessionRepository::new(db.clone()));
+        let (_, api_key) = create(&db, &new_key(1)).await.unwrap();
+
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            let status = ProofSessionStatus::Preparing;
+            results.push(create_test_session_for_key(&sessions, &api_key, status).await);
+        }
+
+        assert!(results[0].is_ok());
+        assert!(results[1].as_ref().unwrap_err().is::<QuotaExceeded>());
+    }
+
+    #[tokio::test]
+    async fn test_only_the_owner_or_an_admin_can_manage_a_session() {
+        let db = connect_test_db().await;
+        let sessions: SharedSessionRepository = Arc::new(SurrealSessionRepository::new(db.clone()));
+        let (_, owner) = create(&db, &new_key(1)).await.unwrap();
+        let (_, other) = create(&db, &new_key(1)).await.unwrap();
+        let (_, admin) = create(
+            &db,
+            &NewApiKey {
+                admin: true,
+                ..new_key(1)
+            },
+        )
+        .await
+        .unwrap();
+
+        let session = create_test_session_for_key(&sessions, &owner, ProofSessionStatus::Completed)
+            .await
+            .unwrap();
+
+        assert!(owner.can_manage(&session));
+        assert!(admin.can_manage(&session));
+        assert!(!other.can_manage(&session));
+    }
+}
@@ -1 +1,9 @@
-pub(crate) mod proof_session;
\ No newline at end of file
+pub(crate) mod api_key;
+pub(crate) mod proof_batch;
+pub(crate) mod proof_session;
+pub(crate) mod retention;
+pub(crate) mod search;
+pub(crate) mod session_bundle;
+pub(crate) mod session_repository;
+pub(crate) mod shutdown;
+pub(crate) mod wasm_journal;
@@ -0,0 +1,304 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::{Datetime, Thing};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    config::config,
+    services::{
+        api_key::ApiKeyRecord,
+        proof_session::{self, ProofSessionArgument, ProofSessionInput, ProofSessionStatus},
+        session_repository::SharedSessionRepository,
+    },
+    utils::ipfs::list_manifest_from_ipfs,
+};
+
+#[derive(Debug, Serialize)]
+pub struct ProofBatch<'a> {
+    pub batch_id: &'a String,
+    pub image_cid: &'a String,
+    pub session_ids: &'a Vec<String>,
+    pub creation: ProofBatchCreation,
+    pub created_at: Datetime,
+}
+
+/// Whether every session of a batch was created. Batches are recorded before their
+/// sessions, so a failure part way still leaves the sessions already started findable.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ProofBatchCreation {
+    Creating,
+    /// Batches recorded before this field existed were always complete.
+    #[default]
+    Created,
+    PartiallyFailed,
+}
+
+/// Outcome of creating the sessions of a batch.
+#[derive(Debug, Serialize)]
+pub struct ProofBatchCreationUpdate<'a> {
+    pub session_ids: &'a Vec<String>,
+    pub creation: ProofBatchCreation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creation_error: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProofBatchRecord {
+    pub(crate) id: Thing,
+    pub batch_id: String,
+    pub image_cid: String,
+    pub session_ids: Vec<String>,
+    #[serde(default)]
+    pub creation: ProofBatchCreation,
+    #[serde(default)]
+    pub creation_error: Option<String>,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ProofBatchProgress {
+    pub total: usize,
+    pub preparing: usize,
+    pub in_progress: usize,
+    pub completed: usize,
+    pub timed_out: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Sessions already deleted by the retention policy.
+    pub collected: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofBatchSessionStatus {
+    pub session_id: String,
+    pub status: ProofSessionStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofBatchStatus {
+    pub batch_id: String,
+    pub image_cid: String,
+    pub creation: ProofBatchCreation,
+    pub creation_error: Option<String>,
+    pub created_at: Datetime,
+    pub is_finished: bool,
+    pub progress: ProofBatchProgress,
+    pub sessions: Vec<ProofBatchSessionStatus>,
+}
+
+pub async fn create(
+    sessions: &SharedSessionRepository,
+    image_cid: &String,
+    arguments: &Vec<Vec<ProofSessionArgument>>,
+    private_inputs: Vec<Option<Vec<u8>>>,
+    api_key: Option<&ApiKeyRecord>,
+) -> Result<ProofBatchRecord, Box<dyn Error>> {
+    let max_batch_size = config().limits.max_batch_size;
+    if arguments.is_empty() || arguments.len() > max_batch_size {
+        return Err(format!(
+            "Batch must contain between 1 and {} sessions.",
+            max_batch_size
+        )
+        .into());
+    }
+    if !private_inputs.is_empty() && private_inputs.len() != arguments.len() {
+        return Err("Batch must have a private input for every session or none.".into());
+    }
+
+    // Fetch the manifest once and share it across every session in the batch
+    let (manifest, files) = list_manifest_from_ipfs(image_cid)
+        .await
+        .map_err(|err| format!("Failed to fetch manifest: {}", err))?;
+
+    // Generate a random batch UUID
+    let batch_id: String = Uuid::new_v4().to_string();
+
+    // Record the batch first, so sessions started before a failure still belong to it
+    sessions
+        .create_batch(ProofBatch {
+            batch_id: &batch_id,
+            image_cid,
+            session_ids: &Vec::new(),
+            creation: ProofBatchCreation::Creating,
+            created_at: Datetime::default(),
+        })
+        .await?;
+
+    let mut session_ids: Vec<String> = Vec::with_capacity(arguments.len());
+    let mut creation_error: Option<String> = None;
+    let mut private_inputs = private_inputs.into_iter();
+    for session_arguments in arguments {
+        let created = proof_session::create_with_manifest(
+            sessions,
+            image_cid,
+            &manifest,
+            &files,
+            ProofSessionInput {
+                arguments: session_arguments,
+                private_input: private_inputs.next().flatten(),
+            },
+            Some(&batch_id),
+            api_key,
+        )
+        .await;
+
+        match created {
+            Ok(record) => session_ids.push(record.session_id),
+            Err(err) => {
+                warn!(
+                    %batch_id,
+                    created = session_ids.len(),
+                    error = %err,
+                    "Batch creation stopped"
+                );
+                creation_error = Some(err.to_string());
+                break;
+            }
+        }
+    }
+
+    let creation = match creation_error {
+        Some(_) => ProofBatchCreation::PartiallyFailed,
+        None => ProofBatchCreation::Created,
+    };
+    let record = sessions
+        .update_batch(
+            &batch_id,
+            ProofBatchCreationUpdate {
+                session_ids: &session_ids,
+                creation,
+                creation_error: creation_error.as_deref(),
+            },
+        )
+        .await?;
+
+    Ok(record)
+}
+
+pub async fn fetch(
+    sessions: &SharedSessionRepository,
+    id: &String,
+) -> Result<ProofBatchStatus, Box<dyn Error>> {
+    let record = sessions.fetch_batch(id).await?;
+
+    let batch_sessions = proof_session::list_by_batch(sessions, &record.batch_id).await?;
+
+    // Aggregate session statuses into batch progress
+    let mut progress = ProofBatchProgress {
+        total: record.session_ids.len(),
+        ..Default::default()
+    };
+    for session in &batch_sessions {
+        match session.status {
+            ProofSessionStatus::Preparing => progress.preparing += 1,
+            ProofSessionStatus::InProgress => progress.in_progress += 1,
+            ProofSessionStatus::Completed => progress.completed += 1,
+            ProofSessionStatus::TimedOut => progress.timed_out += 1,
+            ProofSessionStatus::Failed => progress.failed += 1,
+            ProofSessionStatus::Cancelled => progress.cancelled += 1,
+        }
+    }
+    progress.collected = progress.total.saturating_sub(batch_sessions.len());
+    let finished = progress.completed
+        + progress.timed_out
+        + progress.failed
+        + progress.cancelled
+        + progress.collected;
+
+    Ok(ProofBatchStatus {
+        batch_id: record.batch_id,
+        image_cid: record.image_cid,
+        creation: record.creation,
+        creation_error: record.creation_error,
+        created_at: record.created_at,
+        is_finished: record.creation != ProofBatchCreation::Creating && finished == progress.total,
+        progress,
+        sessions: batch_sessions
+            .into_iter()
+            .map(|session| ProofBatchSessionStatus {
+                session_id: session.session_id,
+                status: session.status,
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        config::init_test_config,
+        services::session_repository::in_memory::{create_test_session, InMemorySessionRepository},
+    };
+
+    #[tokio::test]
+    async fn test_fetch_aggregates_session_statuses() {
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+        let batch_id = "batch".to_string();
+        let image_cid = "image".to_string();
+
+        let mut session_ids = Vec::new();
+        for status in [
+            ProofSessionStatus::Completed,
+            ProofSessionStatus::Failed,
+            ProofSessionStatus::InProgress,
+        ] {
+            let record = create_test_session(&sessions, &image_cid, Some(&batch_id), status, 0);
+            session_ids.push(record.await.session_id);
+        }
+        // Deleted by the retention policy, but still part of the batch
+        session_ids.push("collected".into());
+        sessions
+            .create_batch(ProofBatch {
+                batch_id: &batch_id,
+                image_cid: &image_cid,
+                session_ids: &session_ids,
+                creation: ProofBatchCreation::Created,
+                created_at: Datetime::default(),
+            })
+            .await
+            .unwrap();
+
+        let status = fetch(&sessions, &batch_id).await.unwrap();
+
+        assert_eq!(status.progress.total, 4);
+        assert_eq!(status.progress.completed, 1);
+        assert_eq!(status.progress.failed, 1);
+        assert_eq!(status.progress.in_progress, 1);
+        assert_eq!(status.progress.collected, 1);
+        assert_eq!(status.sessions.len(), 3);
+        assert!(!status.is_finished);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_unknown_batch_is_an_error() {
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+
+        assert!(fetch(&sessions, &"missing".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_private_inputs_must_match_the_sessions() {
+        init_test_config();
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+        let arguments = vec![Vec::new(), Vec::new()];
+
+        let result = create(
+            &sessions,
+            &"image".to_string(),
+            &arguments,
+            vec![Some(vec![1])],
+            None,
+        )
+        .await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Batch must have a private input for every session or none."
+        );
+    }
+}
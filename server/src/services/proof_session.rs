@@ -1,25 +1,58 @@
-use std::{collections::HashMap, error::Error, str::FromStr};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use hex::FromHex;
+use once_cell::sync::Lazy;
 use reqwest::multipart::Part;
 use risc0_zkvm::{
-    default_prover,
     serde::{from_slice, to_vec},
     sha::Digest,
-    ExecutorEnv, MemoryImage, Program, Receipt, ReceiptMetadata, MEM_SIZE, PAGE_SIZE,
+    Executor, ExecutorEnv, MemoryImage, Program, Receipt, ReceiptMetadata, MEM_SIZE, PAGE_SIZE,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use surrealdb::sql::{Datetime, Thing};
-use tokio::task;
+use tokio::{sync::Semaphore, task, time::timeout};
+use tracing::{error, info, info_span, warn, Instrument, Span};
 use uuid::Uuid;
 
 use crate::{
-    db::DB,
+    config::config,
+    db::json_text,
+    metrics::{self, PROOF_PADDED_CYCLES},
+    services::{
+        api_key::ApiKeyRecord,
+        session_repository::SharedSessionRepository,
+        shutdown,
+        wasm_journal::{WasmJournal, WasmOutcome, WasmValue},
+    },
     utils::ipfs::{download_from_ipfs, list_manifest_from_ipfs, upload_to_ipfs},
 };
 
-const SESSION: &str = "session";
+/// Returned when an API key changes a session it neither created nor administers.
+#[derive(Debug)]
+pub struct NotSessionOwner;
+
+impl fmt::Display for NotSessionOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("session belongs to another API key")
+    }
+}
+
+impl Error for NotSessionOwner {}
+
+/// Bounds the proofs running at once, however many sessions or batches are created.
+static PROOF_PERMITS: Lazy<Semaphore> =
+    Lazy::new(|| Semaphore::new(config().prover.max_concurrent_proofs));
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum DynType {
@@ -62,11 +95,11 @@ impl FromStr for DynType {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProofSessionArgument {
-    value: String,
-    arg_type: DynType,
+    pub(crate) value: String,
+    pub(crate) arg_type: DynType,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ProofSessionStatus {
     Preparing,
     InProgress,
@@ -79,51 +112,75 @@ impl ProofSessionStatus {
     fn default() -> Self {
         ProofSessionStatus::Preparing
     }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(
+            self,
+            ProofSessionStatus::Preparing | ProofSessionStatus::InProgress
+        )
+    }
 }
 
 #[derive(Debug, Serialize)]
-struct ProofSession<'a> {
-    session_id: &'a String,
-    is_wasm: bool,
+pub struct ProofSession<'a> {
+    pub session_id: &'a String,
+    pub batch_id: Option<&'a String>,
+    pub api_key_id: Option<&'a String>,
+    pub is_wasm: bool,
+    pub pinned: bool,
 
-    image_id: Option<&'a String>,
-    image_cid: &'a String,
-    receipt_cid: Option<&'a String>,
-    receipt_metadata: Option<&'a ReceiptMetadata>,
+    pub image_id: Option<&'a String>,
+    pub image_cid: &'a String,
+    pub receipt_cid: Option<&'a String>,
+    #[serde(with = "json_text")]
+    pub receipt_metadata: Option<&'a ReceiptMetadata>,
 
-    status: ProofSessionStatus,
-    argument_type: &'a Vec<DynType>,
-    method: &'a String,
-    arguments: &'a Vec<ProofSessionArgument>,
-    result_type: &'a DynType,
+    pub status: ProofSessionStatus,
+    pub argument_type: &'a Vec<DynType>,
+    pub method: &'a String,
+    pub arguments: &'a Vec<ProofSessionArgument>,
+    pub result_type: &'a DynType,
 
-    result: Option<&'a Value>,
+    #[serde(with = "json_text")]
+    pub result: Option<&'a Value>,
 
-    created_at: Datetime,
-    completed_at: Option<Datetime>,
+    pub created_at: Datetime,
+    pub completed_at: Option<Datetime>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ProofSessionCompleteRecord {
-    status: ProofSessionStatus,
-    completed_at: Datetime,
-    image_id: Option<String>,
-    receipt_cid: Option<String>,
-    receipt_metadata: Option<ReceiptMetadata>,
-    result: Option<Value>,
+pub struct ProofSessionCompleteRecord {
+    pub status: ProofSessionStatus,
+    pub completed_at: Datetime,
+    pub image_id: Option<String>,
+    pub receipt_cid: Option<String>,
+    #[serde(with = "json_text")]
+    pub receipt_metadata: Option<ReceiptMetadata>,
+    #[serde(with = "json_text")]
+    pub result: Option<Value>,
+    pub status_reason: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProofSessionRecord {
-    id: Thing,
+    pub(crate) id: Thing,
     pub session_id: String,
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// Only used for ownership and quotas, never part of the public session JSON
+    #[serde(default, skip_serializing)]
+    pub api_key_id: Option<String>,
     pub is_wasm: bool,
+    #[serde(default)]
+    pub pinned: bool,
 
     pub image_id: Option<String>,
     pub image_cid: String,
     pub receipt_cid: Option<String>,
+    #[serde(default, deserialize_with = "json_text::deserialize")]
     pub receipt_metadata: Option<ReceiptMetadata>,
 
+    #[serde(default, deserialize_with = "json_text::deserialize")]
     pub result: Option<Value>,
 
     #[serde(default = "ProofSessionStatus::default")]
@@ -135,16 +192,83 @@ pub struct ProofSessionRecord {
 
     pub created_at: Datetime,
     pub completed_at: Option<Datetime>,
+    #[serde(default)]
+    pub status_reason: Option<String>,
+}
+
+impl ProofSessionRecord {
+    /// Key of the database record, as opposed to the public `session_id`.
+    pub fn record_id(&self) -> String {
+        self.id.id.to_string()
+    }
+}
+
+/// How a WASM image's guest takes its input and what it commits.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GuestKind {
+    /// Built for one method and signature
+    #[default]
+    Typed,
+    /// Reads the method and typed arguments from input, commits a [`WasmJournal`]
+    Universal,
+}
+
+/// Resource limits compiled into a WASM guest.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct WasmLimits {
+    pub(crate) fuel: Option<u64>,
+    pub(crate) max_memory_bytes: Option<usize>,
+}
+
+/// What a guest commits to its journal.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalFormat {
+    /// Only the result, as typed guests built before [`WasmJournal`] commit
+    #[default]
+    Result,
+    /// A [`WasmJournal`] binding the result to the module and the call
+    Wasm,
+}
+
+/// A group of host functions the guest links into the WASM module.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HostImport {
+    /// Reads the session's private input
+    Input,
+    /// Appends bytes to the journal's `committed` data
+    Commit,
+    Log,
+    Sha256,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Manifest {
-    wasm_path: Option<String>,
-    elf_path: String,
-    elf_id: String,
-    method: String,
-    argument_type: Vec<DynType>,
-    result_type: DynType,
+    pub(crate) wasm_path: Option<String>,
+    pub(crate) elf_path: String,
+    pub(crate) elf_id: String,
+    pub(crate) method: String,
+    pub(crate) argument_type: Vec<DynType>,
+    pub(crate) result_type: DynType,
+    #[serde(default)]
+    pub(crate) guest: GuestKind,
+    #[serde(default)]
+    pub(crate) journal: JournalFormat,
+    #[serde(default)]
+    pub(crate) limits: WasmLimits,
+    #[serde(default)]
+    pub(crate) imports: Vec<HostImport>,
+}
+
+impl Manifest {
+    pub(crate) fn journal_format(&self) -> JournalFormat {
+        match self.guest {
+            GuestKind::Universal => JournalFormat::Wasm,
+            GuestKind::Typed => self.journal,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -153,40 +277,84 @@ struct ProofSessionRequest {
     files: HashMap<String, String>,
     arguments: Vec<ProofSessionArgument>,
     result_type: DynType,
+    private_input: Option<Vec<u8>>,
 }
 
-pub async fn list_by_image(image_cid: &String) -> Result<Vec<ProofSessionRecord>, Box<dyn Error>> {
-    let mut response = DB
-        .query("SELECT * FROM type::table($table) WHERE image_cid = $image_cid ORDER BY created_at DESC")
-        .bind(("table", "session"))
-        .bind(("image_cid", image_cid))
-        .await
-        .expect("Failed to find proof sessions.");
-
-    let records: Vec<ProofSessionRecord> = response.take(0).unwrap();
+/// What a session hands the guest. Private input is proven over but never stored.
+pub(crate) struct ProofSessionInput<'a> {
+    pub(crate) arguments: &'a Vec<ProofSessionArgument>,
+    pub(crate) private_input: Option<Vec<u8>>,
+}
 
-    Ok(records)
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofSessionSortOrder {
+    Asc,
+    #[default]
+    Desc,
 }
 
-pub async fn fetch(id: &String) -> Result<ProofSessionRecord, Box<dyn Error>> {
-    let mut response = DB
-        .query("SELECT * FROM type::table($table) WHERE session_id = $session_id")
-        .bind(("table", "session"))
-        .bind(("session_id", id))
-        .await
-        .expect("Failed to find proof sessions.");
+/// Filters, sort order and offset pagination shared by the session listings.
+#[derive(Debug, Deserialize, Default)]
+pub struct ProofSessionFilter {
+    pub status: Option<ProofSessionStatus>,
+    pub is_wasm: Option<bool>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    #[serde(default)]
+    pub order: ProofSessionSortOrder,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
 
-    let record: Option<ProofSessionRecord> = response.take(0).unwrap();
+impl ProofSessionFilter {
+    /// Page size and offset, with the size clamped to the configured limits.
+    pub fn page(&self) -> (usize, usize) {
+        let limits = &config().limits;
+        let limit = self
+            .limit
+            .unwrap_or(limits.default_page_limit)
+            .clamp(1, limits.max_page_limit);
 
-    if let Some(record) = record {
-        Ok(record)
-    } else {
-        Err("Error::RowNotFound".into())
+        (limit, self.offset.unwrap_or(0))
     }
 }
 
-pub async fn verify(id: &String) -> Result<Value, Box<dyn Error>> {
-    let proof_session = fetch(id).await.unwrap();
+pub async fn list_by_image(
+    sessions: &SharedSessionRepository,
+    image_cid: &String,
+    filter: &ProofSessionFilter,
+) -> Result<Vec<ProofSessionRecord>, Box<dyn Error>> {
+    list(sessions, Some(image_cid), filter).await
+}
+
+pub async fn list(
+    sessions: &SharedSessionRepository,
+    image_cid: Option<&String>,
+    filter: &ProofSessionFilter,
+) -> Result<Vec<ProofSessionRecord>, Box<dyn Error>> {
+    Ok(sessions.list(image_cid.map(String::as_str), filter).await?)
+}
+
+pub async fn list_by_batch(
+    sessions: &SharedSessionRepository,
+    batch_id: &String,
+) -> Result<Vec<ProofSessionRecord>, Box<dyn Error>> {
+    Ok(sessions.list_by_batch(batch_id).await?)
+}
+
+pub async fn fetch(
+    sessions: &SharedSessionRepository,
+    id: &String,
+) -> Result<ProofSessionRecord, Box<dyn Error>> {
+    Ok(sessions.fetch(id).await?)
+}
+
+pub async fn verify(
+    sessions: &SharedSessionRepository,
+    id: &String,
+) -> Result<Value, Box<dyn Error>> {
+    let proof_session = fetch(sessions, id).await?;
     let receipt_url = proof_session.receipt_cid.unwrap();
     let receipt_buf = download_from_ipfs(&receipt_url).await.unwrap();
     let receipt: Receipt = bincode::deserialize(&receipt_buf).unwrap();
@@ -196,90 +364,170 @@ pub async fn verify(id: &String) -> Result<Value, Box<dyn Error>> {
         .verify(image_id)
         .expect("Receipt verification failed");
 
-    // Parse result into a JSON value
-    let result: Value = match proof_session.result_type {
-        DynType::Integer | DynType::I32 => {
-            let int_result: i32 = from_slice(&receipt.journal).unwrap();
-            int_result.into()
-        }
-        DynType::Float | DynType::F32 => {
-            let float_result: f32 = from_slice(&receipt.journal).unwrap();
-            float_result.into()
-        }
-        DynType::I64 => {
-            let int_result: i64 = from_slice(&receipt.journal).unwrap();
-            int_result.into()
-        }
-        DynType::F64 => {
-            let int_result: f64 = from_slice(&receipt.journal).unwrap();
-            int_result.into()
+    // Parse result into a JSON value, checking the journal against the image's module
+    let (manifest, files) = list_manifest_from_ipfs(&proof_session.image_cid).await?;
+    let wasm_file = match &manifest.wasm_path {
+        Some(wasm_path) => {
+            let wasm_cid = files
+                .get(wasm_path)
+                .ok_or("WASM file missing from the image.")?;
+            Some(download_from_ipfs(wasm_cid).await?)
         }
+        None => None,
     };
+    let result: Value = journal_result(
+        &manifest,
+        wasm_file.as_deref(),
+        &proof_session.arguments,
+        &proof_session.result_type,
+        &receipt.journal,
+    )
+    .map_err(|err| -> Box<dyn Error> { err })?;
 
     Ok(result)
 }
 
 pub async fn create(
+    sessions: &SharedSessionRepository,
     image_cid: &String,
     arguments: &Vec<ProofSessionArgument>,
+    private_input: Option<Vec<u8>>,
+    api_key: Option<&ApiKeyRecord>,
 ) -> Result<ProofSessionRecord, Box<dyn Error>> {
+    let (manifest, files) = list_manifest_from_ipfs(image_cid)
+        .await
+        .map_err(|err| format!("Failed to fetch manifest: {}", err))?;
+
+    let input = ProofSessionInput {
+        arguments,
+        private_input,
+    };
+    create_with_manifest(sessions, image_cid, &manifest, &files, input, None, api_key).await
+}
+
+/// Creates a proof session from an already fetched manifest, so callers proving
+/// the same image many times (e.g. batches) only download it once.
+pub(crate) async fn create_with_manifest(
+    sessions: &SharedSessionRepository,
+    image_cid: &String,
+    manifest: &Manifest,
+    files: &HashMap<String, String>,
+    input: ProofSessionInput<'_>,
+    batch_id: Option<&String>,
+    api_key: Option<&ApiKeyRecord>,
+) -> Result<ProofSessionRecord, Box<dyn Error>> {
+    let ProofSessionInput {
+        arguments,
+        private_input,
+    } = input;
+    if private_input.is_some() && !manifest.imports.contains(&HostImport::Input) {
+        return Err("Image does not import `input`, so it takes no private input.".into());
+    }
+
     // Generate a random session UUID
     let random_id: String = Uuid::new_v4().to_string();
-    let (manifest, files) = list_manifest_from_ipfs(&image_cid)
-        .await
-        .expect("Failed to fetch manifest.");
-
-    // Create a proof session record
-    let record: ProofSessionRecord = DB
-        .create(SESSION)
-        .content(ProofSession {
-            session_id: &random_id,
-            image_id: None,
-            image_cid,
-            status: ProofSessionStatus::Preparing,
-            is_wasm: manifest.wasm_path.is_some(),
-            receipt_cid: None,
-            argument_type: &manifest.argument_type,
-            method: &manifest.method,
-            result_type: &manifest.result_type,
-            arguments,
-            created_at: Datetime::default(),
-            completed_at: None,
-            receipt_metadata: None,
-            result: None,
-        })
-        .await
-        .unwrap();
 
-    let record_id = record.id.id.clone().to_string();
+    // Create a proof session record, within the quota of its API key
+    let api_key_id = api_key.map(ApiKeyRecord::key_id);
+    let record: ProofSessionRecord = sessions
+        .create(
+            ProofSession {
+                session_id: &random_id,
+                batch_id,
+                api_key_id: api_key_id.as_ref(),
+                image_id: None,
+                image_cid,
+                status: ProofSessionStatus::Preparing,
+                is_wasm: manifest.wasm_path.is_some(),
+                pinned: false,
+                receipt_cid: None,
+                argument_type: &manifest.argument_type,
+                method: &manifest.method,
+                result_type: &manifest.result_type,
+                arguments,
+                created_at: Datetime::default(),
+                completed_at: None,
+                receipt_metadata: None,
+                result: None,
+            },
+            api_key.map(ApiKeyRecord::quota),
+        )
+        .await?;
+
+    metrics::record_session_created(image_cid);
+    let span = info_span!("proof_session", session_id = %random_id, image_cid = %image_cid);
+    span.in_scope(|| info!(batch_id = ?batch_id, "Proof session created"));
+
+    let record_id = record.record_id();
+    let record_image_cid = image_cid.to_string();
     let record_request = ProofSessionRequest {
-        manifest: manifest.into(),
-        files: files.into(),
+        manifest: manifest.clone(),
+        files: files.clone(),
         arguments: arguments.to_vec(),
         result_type: record.result_type.clone(),
+        private_input,
     };
 
-    // Start task in background
-    task::spawn(async {
-        let updated_status;
+    // Start task in background, tracked so shutdown can drain or cancel it
+    let tracked = shutdown::track_session(&record_id, image_cid);
+    let cancelled = tracked.cancelled().clone();
+    let sessions = sessions.clone();
+    let session_task = async move {
+        let mut updated_status;
         let image_id: Option<String>;
         let receipt: Option<Vec<u8>>;
-        let receipt_metadata: Option<ReceiptMetadata>;
-        let receipt_result: Option<Value>;
-        let receipt_cid: Option<String>;
+        let mut receipt_metadata: Option<ReceiptMetadata>;
+        let mut receipt_result: Option<Value>;
+        let mut receipt_cid: Option<String> = None;
+        let mut status_reason: Option<String>;
         let session_id = random_id;
 
+        // Queued sessions stay `Preparing`, the timeout only starts once proving does.
+        // The semaphore is never closed, so acquiring it cannot fail.
+        let _permit = PROOF_PERMITS.acquire().await.ok();
+        let started_at = Instant::now();
+
         // // Proofs
-        match do_prove(record_request).await {
-            Ok((image_id_data, receipt_data, result, metadata)) => {
+        let proved = timeout(
+            config().prover_timeout(),
+            do_prove(record_request, cancelled.clone()),
+        )
+        .await;
+
+        // Shutdown already marked the session cancelled, don't overwrite that
+        if cancelled.load(Ordering::SeqCst) {
+            warn!("Proof finished after the session was cancelled, discarding it");
+            return (ProofSessionStatus::Cancelled, started_at.elapsed());
+        }
+
+        match proved {
+            Ok(Ok((image_id_data, receipt_data, result, metadata))) => {
                 updated_status = ProofSessionStatus::Completed;
                 receipt = Some(receipt_data);
                 image_id = Some(image_id_data);
                 receipt_metadata = Some(metadata);
                 receipt_result = Some(result);
+                status_reason = None;
             }
-            Err(_) => {
+            Ok(Err(err)) => {
+                error!(error = %err, "Proof failed");
                 updated_status = ProofSessionStatus::Failed;
+                status_reason = Some(err.to_string());
+                receipt = None;
+                receipt_metadata = None;
+                image_id = None;
+                receipt_result = None;
+            }
+            Err(_) => {
+                // The prover can't be interrupted mid-proof, it stops at its next check and
+                // whatever it still produces is dropped with the blocking task
+                cancelled.store(true, Ordering::SeqCst);
+                warn!("Proof timed out");
+                updated_status = ProofSessionStatus::TimedOut;
+                status_reason = Some(format!(
+                    "Proof did not complete within the prover timeout of {} seconds.",
+                    config().prover.timeout_secs
+                ));
                 receipt = None;
                 receipt_metadata = None;
                 image_id = None;
@@ -289,35 +537,186 @@ pub async fn create(
 
         if let Some(receipt) = receipt {
             let file_name = format!("{}_receipt.bin", session_id);
-            let part = Part::bytes(receipt.to_vec())
+            let part = Part::bytes(receipt)
                 .file_name(file_name.to_string())
                 .mime_str("application/bincode")
                 .unwrap();
-            receipt_cid = Some(upload_to_ipfs(&file_name, part).await.unwrap());
-        } else {
-            receipt_cid = None
+            match upload_to_ipfs(&file_name, part).await {
+                Ok(cid) => receipt_cid = Some(cid),
+                Err(err) => {
+                    // Without its receipt the proof can't be verified, so it didn't succeed
+                    error!(error = %err, "Failed to upload the receipt");
+                    updated_status = ProofSessionStatus::Failed;
+                    status_reason = Some(format!("Failed to upload the receipt: {}", err));
+                    receipt_metadata = None;
+                    receipt_result = None;
+                }
+            }
         }
 
-        // TODO: Update session data
-        let _: ProofSessionRecord = DB
-            .update((SESSION, record_id))
-            .merge(ProofSessionCompleteRecord {
-                status: updated_status,
-                completed_at: Datetime::default(),
-                image_id,
-                receipt_cid,
-                receipt_metadata,
-                result: receipt_result,
-            })
+        let elapsed = started_at.elapsed();
+        info!(status = ?updated_status, ?elapsed, "Proof session finished");
+
+        let completed = sessions
+            .complete(
+                &record_id,
+                ProofSessionCompleteRecord {
+                    status: updated_status.clone(),
+                    completed_at: Datetime::default(),
+                    image_id,
+                    receipt_cid,
+                    receipt_metadata,
+                    result: receipt_result,
+                    status_reason,
+                },
+            )
             .await
-            .expect("Failed to update proof session status");
-    });
+            .map_err(|err| err.to_string());
+        if let Err(err) = completed {
+            error!(error = %err, "Failed to record the proof session's outcome");
+            updated_status = ProofSessionStatus::Failed;
+            let reason = format!("Failed to record the outcome: {}", err);
+            if let Err(err) = sessions
+                .update_status(&record_id, updated_status.clone(), Some(&reason))
+                .await
+            {
+                error!(error = %err, "Failed to mark the proof session as failed");
+            }
+        }
+
+        (updated_status, elapsed)
+    };
+    task::spawn(
+        async move {
+            let (status, elapsed) = session_task.await;
+            // A session drained at shutdown was already counted as cancelled. Should the
+            // task panic instead, dropping `tracked` still untracks the session.
+            if tracked.untrack() {
+                metrics::record_session_finished(&record_image_cid, &status, elapsed);
+            }
+        }
+        .instrument(span),
+    );
 
     Ok(record)
 }
 
+/// Pins or unpins a session on behalf of `api_key`, failing with [`NotSessionOwner`] unless
+/// the key may manage it. Pinned sessions are exempt from the retention policy.
+pub async fn set_pinned(
+    sessions: &SharedSessionRepository,
+    id: &String,
+    pinned: bool,
+    api_key: &ApiKeyRecord,
+) -> Result<ProofSessionRecord, Box<dyn Error>> {
+    let session = sessions.fetch(id).await?;
+    if !api_key.can_manage(&session) {
+        return Err(NotSessionOwner.into());
+    }
+
+    Ok(sessions.set_pinned(id, pinned).await?)
+}
+
+/// Marks an unfinished session as cancelled, recording why it was stopped.
+pub(crate) async fn cancel(
+    sessions: &SharedSessionRepository,
+    record_id: &String,
+    reason: &str,
+) -> Result<(), Box<dyn Error>> {
+    sessions
+        .update_status(record_id, ProofSessionStatus::Cancelled, Some(reason))
+        .await?;
+
+    Ok(())
+}
+
+/// Computes the hex encoded image ID of an ELF binary, as committed to by its receipts.
+pub(crate) fn compute_image_id(elf_file: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let program = Program::load_elf(elf_file, MEM_SIZE as u32)?;
+    let image = MemoryImage::new(&program, PAGE_SIZE as u32)?;
+
+    Ok(hex::encode(image.compute_id()))
+}
+
+/// Decodes a receipt journal into a JSON value of the given result type.
+pub(crate) fn parse_journal(
+    result_type: &DynType,
+    journal: &[u8],
+) -> Result<Value, risc0_zkvm::serde::Error> {
+    let result: Value = match result_type {
+        DynType::Integer | DynType::I32 => {
+            let int_result: i32 = from_slice(journal)?;
+            int_result.into()
+        }
+        DynType::Float | DynType::F32 => {
+            let float_result: f32 = from_slice(journal)?;
+            float_result.into()
+        }
+        DynType::I64 => {
+            let int_result: i64 = from_slice(journal)?;
+            int_result.into()
+        }
+        DynType::F64 => {
+            let float_result: f64 = from_slice(journal)?;
+            float_result.into()
+        }
+    };
+
+    Ok(result)
+}
+
+/// Version of the object results below, bumped whenever their fields change.
+const RESULT_FORMAT_VERSION: u32 = 1;
+
+/// Decodes the result from a receipt journal, in the format the image's guest commits.
+/// A [`WasmJournal`] must name the image's WASM module and the session's call.
+///
+/// The result is the returned value itself, except for
+/// - images importing `commit`: `{"version", "value", "committed"}`, with the committed
+///   bytes hex encoded
+/// - calls that ran out of fuel: `{"version", "out_of_fuel": true, "fuel_limit"}`
+/// - calls whose result can't be committed: `{"version", "unsupported"}`, with the reason
+pub(crate) fn journal_result(
+    manifest: &Manifest,
+    wasm_file: Option<&[u8]>,
+    arguments: &[ProofSessionArgument],
+    result_type: &DynType,
+    journal: &[u8],
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    match manifest.journal_format() {
+        JournalFormat::Result => Ok(parse_journal(result_type, journal)?),
+        JournalFormat::Wasm => {
+            let journal = WasmJournal::decode(journal)?;
+            journal.check_module(wasm_file.ok_or("Image has no WASM file to check against.")?)?;
+            journal.check_call(&manifest.method, arguments)?;
+
+            match journal.result {
+                WasmOutcome::Returned(result) if manifest.imports.contains(&HostImport::Commit) => {
+                    Ok(json!({
+                        "version": RESULT_FORMAT_VERSION,
+                        "value": result.to_json(),
+                        "committed": hex::encode(&journal.committed),
+                    }))
+                }
+                WasmOutcome::Returned(result) => Ok(result.to_json()),
+                // A proven outcome like any other, so the session completes with it
+                WasmOutcome::OutOfFuel => Ok(json!({
+                    "version": RESULT_FORMAT_VERSION,
+                    "out_of_fuel": true,
+                    "fuel_limit": manifest.limits.fuel,
+                })),
+                WasmOutcome::Unsupported(reason) => Ok(json!({
+                    "version": RESULT_FORMAT_VERSION,
+                    "unsupported": reason,
+                })),
+            }
+        }
+    }
+}
+
 async fn do_prove(
     payload: ProofSessionRequest,
+    cancelled: Arc<AtomicBool>,
 ) -> Result<(String, Vec<u8>, Value, risc0_zkvm::ReceiptMetadata), Box<dyn Error>> {
     // Add WASM
     let wasm_file: Option<Vec<u8>>;
@@ -330,68 +729,119 @@ async fn do_prove(
     // Add ELF Binary
     let elf_file: Vec<u8> =
         download_from_ipfs(payload.files.get(&payload.manifest.elf_path).unwrap()).await?;
-    let mut env_builder = ExecutorEnv::builder();
+    let mut inputs: Vec<Vec<u32>> = Vec::new();
 
-    if wasm_file.is_some() {
-        env_builder.add_input(&to_vec(&wasm_file.unwrap()).unwrap());
+    if let Some(wasm_file) = &wasm_file {
+        inputs.push(to_vec(wasm_file)?);
     }
 
     // Add params
-    for arg in payload.arguments {
-        match arg.arg_type {
-            DynType::Integer | DynType::I32 => {
-                let value: i32 = arg.value.parse().expect("Should parse int.");
-                env_builder.add_input(&to_vec(&value).unwrap());
-            }
-            DynType::Float | DynType::F32 => {
-                let value: f32 = arg.value.parse().expect("Should parse float.");
-                env_builder.add_input(&to_vec(&value).unwrap());
-            }
-            DynType::I64 => {
-                let value: i64 = arg.value.parse().expect("Should parse int.");
-                env_builder.add_input(&to_vec(&value).unwrap());
-            }
-            DynType::F64 => {
-                let value: f64 = arg.value.parse().expect("Should parse int.");
-                env_builder.add_input(&to_vec(&value).unwrap());
+    let arguments = payload
+        .arguments
+        .iter()
+        .map(WasmValue::parse)
+        .collect::<Result<Vec<WasmValue>, _>>()
+        .map_err(|err| -> Box<dyn Error> { err })?;
+    match payload.manifest.guest {
+        GuestKind::Typed => {
+            for argument in &arguments {
+                inputs.push(match argument {
+                    WasmValue::I32(value) => to_vec(value)?,
+                    WasmValue::I64(value) => to_vec(value)?,
+                    WasmValue::F32(value) => to_vec(value)?,
+                    WasmValue::F64(value) => to_vec(value)?,
+                });
             }
         }
+        GuestKind::Universal => {
+            inputs.push(to_vec(&payload.manifest.method)?);
+            inputs.push(to_vec(&arguments)?);
+        }
     }
 
-    // Obtain the default prover.
-    let prover = default_prover();
+    // Private input goes last, only guests importing `input` read it
+    if payload.manifest.imports.contains(&HostImport::Input) {
+        inputs.push(to_vec(
+            payload.private_input.as_deref().unwrap_or_default(),
+        )?);
+    }
 
-    let program = Program::load_elf(&elf_file, MEM_SIZE as u32).expect("Failed to execute proof.");
-    let image = MemoryImage::new(&program, PAGE_SIZE as u32)?;
-    let image_id = hex::encode(image.compute_id());
+    // Prove on a blocking thread, so the session timeout can fire while proving
+    let span = Span::current();
+    task::spawn_blocking(move || {
+        span.in_scope(|| {
+            prove_elf(
+                &elf_file,
+                wasm_file.as_deref(),
+                &inputs,
+                &payload.manifest,
+                &payload.arguments,
+                &payload.result_type,
+                &cancelled,
+            )
+        })
+    })
+    .await?
+    .map_err(|err| -> Box<dyn Error> { err })
+}
 
-    // Produce a receipt by proving the specified ELF binary.
-    let receipt = prover
-        .prove_elf(env_builder.build().unwrap(), &elf_file)
-        .unwrap();
+/// Checked by the prover between steps, since a blocking thread can't be aborted.
+fn ensure_not_cancelled(cancelled: &AtomicBool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err("Proof cancelled.".into());
+    }
+
+    Ok(())
+}
+
+fn prove_elf(
+    elf_file: &Vec<u8>,
+    wasm_file: Option<&[u8]>,
+    inputs: &Vec<Vec<u32>>,
+    manifest: &Manifest,
+    arguments: &[ProofSessionArgument],
+    result_type: &DynType,
+    cancelled: &AtomicBool,
+) -> Result<(String, Vec<u8>, Value, risc0_zkvm::ReceiptMetadata), Box<dyn Error + Send + Sync>> {
+    let mut env_builder = ExecutorEnv::builder();
+    for input in inputs {
+        env_builder.add_input(input);
+    }
+
+    let image_id = compute_image_id(elf_file)?;
+
+    // Execute the ELF binary first, so the cycle count can be recorded.
+    ensure_not_cancelled(cancelled)?;
+    let mut exec = Executor::from_elf(env_builder.build().unwrap(), elf_file)?;
+    let session = exec.run()?;
+    let padded_cycles = session
+        .segments
+        .iter()
+        .map(|segment| segment.resolve().map(|segment| 1u64 << segment.po2))
+        .sum::<Result<u64, _>>()?;
+    PROOF_PADDED_CYCLES.observe(padded_cycles as f64);
+    info!(
+        padded_cycles,
+        segments = session.segments.len(),
+        "Executed session, proving"
+    );
+
+    // Produce a receipt by proving the executed session. This is the long step, and
+    // the last chance to stop before it.
+    ensure_not_cancelled(cancelled)?;
+    let receipt = session.prove().unwrap();
     receipt
-        .verify(Digest::from_hex(&payload.manifest.elf_id).unwrap())
+        .verify(Digest::from_hex(&manifest.elf_id).unwrap())
         .unwrap();
 
     // Parse result into a JSON value
-    let result: Value = match payload.result_type {
-        DynType::Integer | DynType::I32 => {
-            let int_result: i32 = from_slice(&receipt.journal).unwrap();
-            int_result.into()
-        }
-        DynType::Float | DynType::F32 => {
-            let float_result: f32 = from_slice(&receipt.journal).unwrap();
-            float_result.into()
-        }
-        DynType::I64 => {
-            let int_result: i64 = from_slice(&receipt.journal).unwrap();
-            int_result.into()
-        }
-        DynType::F64 => {
-            let int_result: f64 = from_slice(&receipt.journal).unwrap();
-            int_result.into()
-        }
-    };
+    let result: Value = journal_result(
+        manifest,
+        wasm_file,
+        arguments,
+        result_type,
+        &receipt.journal,
+    )?;
 
     let metadata: risc0_zkvm::ReceiptMetadata = receipt.get_metadata().unwrap();
 
@@ -400,3 +850,78 @@ async fn do_prove(
 
     Ok((image_id, receipt_data, result, metadata))
 }
+
+#[cfg(test)]
+mod tests {
+    use risc0_zkvm::sha::{Impl, Sha256};
+
+    use super::*;
+    use crate::services::session_repository::in_memory::{
+        create_test_session, InMemorySessionRepository,
+    };
+
+    #[test]
+    fn test_out_of_fuel_is_a_result() {
+        let wasm_file = b"\0asm";
+        let manifest: Manifest = serde_json::from_value(json!({
+            "wasm_path": "zk.wasm",
+            "elf_path": "elf",
+            "elf_id": "",
+            "method": "zkmain",
+            "argument_type": ["I32"],
+            "result_type": "I32",
+            "guest": "universal",
+            "limits": { "fuel": 1000, "max_memory_bytes": null },
+        }))
+        .unwrap();
+        let arguments = vec![ProofSessionArgument {
+            value: "1".into(),
+            arg_type: DynType::I32,
+        }];
+        let journal = WasmJournal {
+            wasm_sha256: *Impl::hash_bytes(wasm_file),
+            method: "zkmain".into(),
+            arguments: vec![WasmValue::I32(1)],
+            committed: Vec::new(),
+            result: WasmOutcome::OutOfFuel,
+        };
+        let journal: Vec<u8> = to_vec(&journal)
+            .unwrap()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+
+        let result = journal_result(
+            &manifest,
+            Some(wasm_file),
+            &arguments,
+            &DynType::I32,
+            &journal,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            json!({ "version": 1, "out_of_fuel": true, "fuel_limit": 1000 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_records_the_reason() {
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+        let record =
+            create_test_session(&sessions, "image", None, ProofSessionStatus::InProgress, 0).await;
+
+        cancel(&sessions, &record.record_id(), "Stopped by a test.")
+            .await
+            .unwrap();
+
+        let cancelled = fetch(&sessions, &record.session_id).await.unwrap();
+        assert_eq!(cancelled.status, ProofSessionStatus::Cancelled);
+        assert_eq!(
+            cancelled.status_reason.as_deref(),
+            Some("Stopped by a test.")
+        );
+        assert!(cancelled.completed_at.is_some());
+    }
+}
@@ -0,0 +1,316 @@
+use std::error::Error;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use surrealdb::sql::Datetime;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{config, RetentionConfig},
+    metrics::{RECEIPTS_DELETED, SESSIONS_COLLECTED},
+    services::session_repository::SharedSessionRepository,
+    utils::ipfs::{can_delete_from_ipfs, delete_from_ipfs},
+};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionReason {
+    MaxAge,
+    MaxSessionsPerImage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionCandidate {
+    pub session_id: String,
+    pub image_cid: String,
+    pub receipt_cid: Option<String>,
+    pub created_at: Datetime,
+    pub reason: RetentionReason,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub max_age_days: u64,
+    pub max_sessions_per_image: usize,
+    /// Whether receipts are deleted from storage along with their sessions.
+    pub deletes_receipts: bool,
+    pub sessions_collected: usize,
+    pub receipts_deleted: usize,
+    /// Only listed on dry runs, a real run deletes them as it goes.
+    pub candidates: Vec<RetentionCandidate>,
+}
+
+/// Sessions fetched per query, so a run never loads the whole table.
+const PAGE_SIZE: usize = 500;
+
+/// Reads the sessions the retention policy would collect a page at a time.
+struct CandidatePages {
+    max_age_days: u64,
+    max_sessions_per_image: usize,
+    cutoff: DateTime<Utc>,
+    created_before: Option<Datetime>,
+    current_image: Option<String>,
+    position: usize,
+    offset: usize,
+    done: bool,
+}
+
+impl CandidatePages {
+    fn new(retention: &RetentionConfig) -> Self {
+        let cutoff = Utc::now() - Duration::days(retention.max_age_days as i64);
+
+        CandidatePages {
+            max_age_days: retention.max_age_days,
+            max_sessions_per_image: retention.max_sessions_per_image,
+            cutoff,
+            // Without a per-image limit only old sessions can be collected, so the query
+            // skips the rest. With one, every session is needed to tell its position
+            // within its image.
+            created_before: (retention.max_sessions_per_image == 0).then(|| cutoff.into()),
+            current_image: None,
+            position: 0,
+            offset: 0,
+            done: retention.max_age_days == 0 && retention.max_sessions_per_image == 0,
+        }
+    }
+
+    /// Candidates of the next page, `None` once every session was read.
+    async fn next(
+        &mut self,
+        sessions: &SharedSessionRepository,
+    ) -> Result<Option<Vec<RetentionCandidate>>, Box<dyn Error>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let page = sessions
+            .list_collectable(self.created_before.as_ref(), PAGE_SIZE, self.offset)
+            .await?;
+        self.offset += page.len();
+        self.done = page.len() < PAGE_SIZE;
+
+        // Sessions come grouped by image and newest first, so the position within
+        // an image tells how many newer sessions it has
+        let mut candidates = Vec::new();
+        for session in page {
+            if self.current_image.as_ref() != Some(&session.image_cid) {
+                self.current_image = Some(session.image_cid.clone());
+                self.position = 0;
+            }
+            self.position += 1;
+
+            let reason = if self.max_age_days > 0 && session.created_at.0 < self.cutoff {
+                RetentionReason::MaxAge
+            } else if self.max_sessions_per_image > 0 && self.position > self.max_sessions_per_image
+            {
+                RetentionReason::MaxSessionsPerImage
+            } else {
+                continue;
+            };
+
+            candidates.push(RetentionCandidate {
+                session_id: session.session_id,
+                image_cid: session.image_cid,
+                receipt_cid: session.receipt_cid,
+                created_at: session.created_at,
+                reason,
+            });
+        }
+
+        Ok(Some(candidates))
+    }
+
+    /// Accounts for sessions already read that were deleted, moving the later ones up.
+    fn deleted(&mut self, count: usize) {
+        self.offset = self.offset.saturating_sub(count);
+    }
+}
+
+/// Finds the sessions the retention policy would collect right now.
+pub async fn plan(
+    sessions: &SharedSessionRepository,
+) -> Result<Vec<RetentionCandidate>, Box<dyn Error>> {
+    let mut pages = CandidatePages::new(&config().retention);
+    let mut candidates = Vec::new();
+    while let Some(page) = pages.next(sessions).await? {
+        candidates.extend(page);
+    }
+
+    Ok(candidates)
+}
+
+/// Applies the retention policy a page at a time, or only reports what it would collect
+/// on a dry run. Only dry runs list the candidates.
+pub async fn collect(
+    sessions: &SharedSessionRepository,
+    dry_run: bool,
+) -> Result<RetentionReport, Box<dyn Error>> {
+    let retention = &config().retention;
+    let deletes_receipts = retention.delete_receipts && can_delete_from_ipfs();
+
+    let mut report = RetentionReport {
+        dry_run,
+        max_age_days: retention.max_age_days,
+        max_sessions_per_image: retention.max_sessions_per_image,
+        deletes_receipts,
+        sessions_collected: 0,
+        receipts_deleted: 0,
+        candidates: Vec::new(),
+    };
+    if dry_run {
+        report.candidates = plan(sessions).await?;
+        return Ok(report);
+    }
+
+    let mut pages = CandidatePages::new(retention);
+    while let Some(page) = pages.next(sessions).await? {
+        if page.is_empty() {
+            continue;
+        }
+
+        // Delete the rows first, a receipt left behind is cheaper than a session pointing
+        // nowhere
+        let session_ids: Vec<String> = page
+            .iter()
+            .map(|candidate| candidate.session_id.clone())
+            .collect();
+        let sessions_collected = sessions.delete(&session_ids).await?;
+        pages.deleted(sessions_collected);
+        report.sessions_collected += sessions_collected;
+        SESSIONS_COLLECTED.inc_by(sessions_collected as u64);
+
+        if deletes_receipts {
+            let mut receipts_deleted = 0;
+            for receipt_cid in page.iter().filter_map(|c| c.receipt_cid.as_ref()) {
+                match delete_from_ipfs(receipt_cid).await {
+                    Ok(()) => receipts_deleted += 1,
+                    Err(err) => warn!(%receipt_cid, error = %err, "Failed to delete receipt"),
+                }
+            }
+            report.receipts_deleted += receipts_deleted;
+            RECEIPTS_DELETED.inc_by(receipts_deleted as u64);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Runs the retention policy every `retention.interval_secs` until the process exits.
+pub async fn run_periodically(sessions: SharedSessionRepository) {
+    let mut ticker = interval(config().retention_interval());
+    loop {
+        ticker.tick().await;
+
+        match collect(&sessions, false).await {
+            Ok(report) => info!(
+                sessions_collected = report.sessions_collected,
+                receipts_deleted = report.receipts_deleted,
+                "Retention run finished"
+            ),
+            Err(err) => error!(error = %err, "Retention run failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        config::init_test_config,
+        services::{
+            proof_session::ProofSessionStatus,
+            session_repository::in_memory::{create_test_session, InMemorySessionRepository},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_plan_collects_old_finished_unpinned_sessions() {
+        let max_age_days = init_test_config().retention.max_age_days as i64;
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+        let old_age = max_age_days + 1;
+
+        let old = create_test_session(
+            &sessions,
+            "image",
+            None,
+            ProofSessionStatus::Completed,
+            old_age,
+        )
+        .await;
+        create_test_session(&sessions, "image", None, ProofSessionStatus::Completed, 0).await;
+        create_test_session(
+            &sessions,
+            "image",
+            None,
+            ProofSessionStatus::InProgress,
+            old_age,
+        )
+        .await;
+        let pinned = create_test_session(
+            &sessions,
+            "image",
+            None,
+            ProofSessionStatus::Failed,
+            old_age,
+        )
+        .await;
+        sessions.set_pinned(&pinned.session_id, true).await.unwrap();
+
+        let candidates = plan(&sessions).await.unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].session_id, old.session_id);
+        assert_eq!(candidates[0].reason, RetentionReason::MaxAge);
+    }
+
+    #[tokio::test]
+    async fn test_plan_reads_every_page() {
+        let max_age_days = init_test_config().retention.max_age_days as i64;
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+
+        for _ in 0..PAGE_SIZE + 1 {
+            create_test_session(
+                &sessions,
+                "image",
+                None,
+                ProofSessionStatus::Completed,
+                max_age_days + 1,
+            )
+            .await;
+        }
+
+        let candidates = plan(&sessions).await.unwrap();
+
+        assert_eq!(candidates.len(), PAGE_SIZE + 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_deletes_every_page() {
+        let max_age_days = init_test_config().retention.max_age_days as i64;
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+
+        for _ in 0..2 * PAGE_SIZE + 1 {
+            create_test_session(
+                &sessions,
+                "image",
+                None,
+                ProofSessionStatus::Completed,
+                max_age_days + 1,
+            )
+            .await;
+        }
+        let kept =
+            create_test_session(&sessions, "image", None, ProofSessionStatus::Completed, 0).await;
+
+        let report = collect(&sessions, false).await.unwrap();
+
+        assert_eq!(report.sessions_collected, 2 * PAGE_SIZE + 1);
+        assert!(report.candidates.is_empty());
+        assert!(plan(&sessions).await.unwrap().is_empty());
+        assert!(sessions.fetch(&kept.session_id).await.is_ok());
+    }
+}
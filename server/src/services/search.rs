@@ -0,0 +1,170 @@
+use std::{collections::HashSet, error::Error};
+
+use serde::Serialize;
+
+use crate::services::{
+    proof_session::ProofSessionRecord, session_repository::SharedSessionRepository,
+};
+
+const MIN_QUERY_LENGTH: usize = 3;
+const MAX_CANDIDATES: usize = 200;
+const MAX_RESULTS: usize = 50;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SearchResultKind {
+    Session,
+    Image,
+    ImageId,
+    Receipt,
+    Method,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub kind: SearchResultKind,
+    /// The matched value, e.g. the session ID or the receipt CID.
+    pub value: String,
+    pub image_cid: String,
+    pub session_id: Option<String>,
+    /// 3 for an exact match, 2 for a prefix match and 1 for a substring match.
+    pub rank: u8,
+}
+
+fn match_rank(value: &str, query: &str) -> Option<u8> {
+    if value == query {
+        Some(3)
+    } else if value.starts_with(query) {
+        Some(2)
+    } else if value.contains(query) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+pub async fn search(
+    sessions: &SharedSessionRepository,
+    query: &str,
+) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+    let query = query.trim();
+    if query.len() < MIN_QUERY_LENGTH {
+        return Ok(Vec::new());
+    }
+
+    // Session IDs, image IDs and method names are matched case-insensitively, CIDs are not.
+    let query_lower = query.to_lowercase();
+
+    // Exact matches are fetched on their own, so newer fuzzy matches can't crowd them out
+    let mut records = sessions.search_exact(query, MAX_CANDIDATES).await?;
+    let exact_ids: HashSet<String> = records.iter().map(|r| r.session_id.clone()).collect();
+    records.extend(
+        sessions
+            .search(query, MAX_CANDIDATES)
+            .await?
+            .into_iter()
+            .filter(|r| !exact_ids.contains(&r.session_id)),
+    );
+
+    // Deduplicate images, image IDs and methods that are shared across sessions
+    let mut seen: HashSet<(SearchResultKind, String, String)> = HashSet::new();
+    let mut results: Vec<SearchResult> = Vec::new();
+    let mut push =
+        |kind: SearchResultKind, value: &String, rank: u8, record: &ProofSessionRecord| {
+            let session_id = match kind {
+                SearchResultKind::Session | SearchResultKind::Receipt => {
+                    Some(record.session_id.to_string())
+                }
+                _ => None,
+            };
+            let key = (kind, value.to_string(), record.image_cid.to_string());
+            if seen.insert(key) {
+                results.push(SearchResult {
+                    kind,
+                    value: value.to_string(),
+                    image_cid: record.image_cid.to_string(),
+                    session_id,
+                    rank,
+                });
+            }
+        };
+
+    for record in &records {
+        if let Some(rank) = match_rank(&record.session_id, &query_lower).filter(|r| *r > 1) {
+            push(SearchResultKind::Session, &record.session_id, rank, record);
+        }
+        if let Some(rank) = match_rank(&record.image_cid, query).filter(|r| *r > 1) {
+            push(SearchResultKind::Image, &record.image_cid, rank, record);
+        }
+        if let Some(image_id) = &record.image_id {
+            if let Some(rank) = match_rank(image_id, &query_lower).filter(|r| *r > 1) {
+                push(SearchResultKind::ImageId, image_id, rank, record);
+            }
+        }
+        if let Some(receipt_cid) = &record.receipt_cid {
+            if let Some(rank) = match_rank(receipt_cid, query).filter(|r| *r > 1) {
+                push(SearchResultKind::Receipt, receipt_cid, rank, record);
+            }
+        }
+        if let Some(rank) = match_rank(&record.method.to_lowercase(), &query_lower) {
+            push(SearchResultKind::Method, &record.method, rank, record);
+        }
+    }
+
+    // Best matches first, then by entity kind
+    results.sort_by(|a, b| b.rank.cmp(&a.rank).then(a.kind.cmp(&b.kind)));
+    results.truncate(MAX_RESULTS);
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::services::{
+        proof_session::ProofSessionStatus,
+        session_repository::in_memory::{create_test_session, InMemorySessionRepository},
+    };
+
+    #[tokio::test]
+    async fn test_exact_session_id_ranks_first() {
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+        let record = create_test_session(
+            &sessions,
+            "bafyimage",
+            None,
+            ProofSessionStatus::Completed,
+            0,
+        )
+        .await;
+
+        let results = search(&sessions, &record.session_id).await.unwrap();
+
+        assert_eq!(results[0].kind, SearchResultKind::Session);
+        assert_eq!(results[0].value, record.session_id);
+        assert_eq!(results[0].rank, 3);
+    }
+
+    #[tokio::test]
+    async fn test_exact_match_survives_newer_fuzzy_matches() {
+        let sessions: SharedSessionRepository = Arc::new(InMemorySessionRepository::default());
+        create_test_session(&sessions, "bafyold", None, ProofSessionStatus::Completed, 7).await;
+        for _ in 0..MAX_CANDIDATES + 10 {
+            create_test_session(
+                &sessions,
+                "bafyolder",
+                None,
+                ProofSessionStatus::Completed,
+                0,
+            )
+            .await;
+        }
+
+        let results = search(&sessions, "bafyold").await.unwrap();
+
+        assert_eq!(results[0].kind, SearchResultKind::Image);
+        assert_eq!(results[0].value, "bafyold");
+        assert_eq!(results[0].rank, 3);
+    }
+}
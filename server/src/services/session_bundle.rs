@@ -0,0 +1,392 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io::Read,
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hex::FromHex;
+use reqwest::multipart::Part;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use risc0_zkvm::{sha::Digest, Receipt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use surrealdb::sql::Datetime;
+use tar::{Archive, Builder, Header};
+use tokio::task;
+use tracing::info;
+use unixfs::directory_cid;
+
+use crate::{
+    config::config,
+    services::{
+        proof_session::{
+            self, compute_image_id, journal_result, DynType, Manifest, ProofSession,
+            ProofSessionArgument, ProofSessionRecord, ProofSessionStatus,
+        },
+        session_repository::SharedSessionRepository,
+    },
+    utils::ipfs::{download_from_ipfs, list_manifest_from_ipfs, upload_to_ipfs},
+};
+
+const BUNDLE_FORMAT_VERSION: u32 = 2;
+const BUNDLE_INDEX: &str = "bundle.json";
+const BUNDLE_SIGNATURE: &str = "bundle.sig";
+const SESSION_ENTRY: &str = "session.json";
+const RECEIPT_ENTRY: &str = "receipt.bin";
+const MANIFEST_FILE: &str = "manifest.json";
+const FILES_DIR: &str = "files/";
+
+/// Table of contents of a bundle, with the SHA-256 of every other entry.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleIndex {
+    format_version: u32,
+    session_id: String,
+    image_cid: String,
+    image_id: String,
+    files: BTreeMap<String, String>,
+}
+
+/// Ed25519 signature over the exact bytes of the index, with the key that made it.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleSignature {
+    public_key: String,
+    signature: String,
+}
+
+/// The portable part of a session; everything else is recomputed on import.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleSession {
+    session_id: String,
+    image_cid: String,
+    image_id: String,
+    method: String,
+    arguments: Vec<ProofSessionArgument>,
+    result_type: DynType,
+    result: Option<serde_json::Value>,
+    created_at: DateTime<Utc>,
+    completed_at: Option<DateTime<Utc>>,
+}
+
+/// Image files live under `files/`, so they cannot clash with the bundle's own entries.
+fn file_entry(path: &str) -> String {
+    format!("{}{}", FILES_DIR, path)
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn write_archive(entries: &BTreeMap<String, Vec<u8>>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, data) in entries {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data.as_slice())?;
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+/// Unpacks a bundle, failing once more than `max_unpacked_bytes` were decompressed.
+fn read_archive(
+    bundle: &[u8],
+    max_unpacked_bytes: u64,
+) -> Result<HashMap<String, Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    let mut entries = HashMap::new();
+    // One byte over the limit tells a bundle of exactly the limit from a larger one
+    let mut archive = Archive::new(GzDecoder::new(bundle).take(max_unpacked_bytes + 1));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.insert(path, data);
+    }
+    if archive.into_inner().limit() == 0 {
+        return Err(format!("Bundle unpacks to more than {} bytes.", max_unpacked_bytes).into());
+    }
+
+    Ok(entries)
+}
+
+fn signing_key(seed: &str) -> Result<Ed25519KeyPair, Box<dyn Error>> {
+    Ed25519KeyPair::from_seed_unchecked(&hex::decode(seed)?)
+        .map_err(|err| format!("Invalid bundle signing key: {}", err).into())
+}
+
+fn sign_index(key_pair: &Ed25519KeyPair, index: &[u8]) -> BundleSignature {
+    BundleSignature {
+        public_key: hex::encode(key_pair.public_key()),
+        signature: hex::encode(key_pair.sign(index)),
+    }
+}
+
+/// Checks the index was signed by one of `trusted_keys`, hex encoded public keys.
+fn verify_index(
+    index: &[u8],
+    signature: &BundleSignature,
+    trusted_keys: &[String],
+) -> Result<(), Box<dyn Error>> {
+    if !trusted_keys
+        .iter()
+        .any(|key| key.eq_ignore_ascii_case(&signature.public_key))
+    {
+        return Err("Bundle is signed by an untrusted key.".into());
+    }
+
+    UnparsedPublicKey::new(&ED25519, hex::decode(&signature.public_key)?)
+        .verify(index, &hex::decode(&signature.signature)?)
+        .map_err(|_| "Invalid bundle signature.".into())
+}
+
+/// The configured trusted keys, plus the public key of this server's own signing key.
+fn trusted_keys() -> Result<Vec<String>, Box<dyn Error>> {
+    let bundles = &config().bundles;
+    let mut keys = bundles.trusted_keys.clone();
+    if let Some(seed) = &bundles.signing_key {
+        keys.push(hex::encode(signing_key(seed)?.public_key()));
+    }
+
+    Ok(keys)
+}
+
+/// Packs a completed session with every file of its image and its receipt into a signed
+/// `.tar.gz`.
+pub async fn export(
+    sessions: &SharedSessionRepository,
+    id: &String,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key_pair = signing_key(
+        config()
+            .bundles
+            .signing_key
+            .as_ref()
+            .ok_or("Bundle signing is not configured.")?,
+    )?;
+    let proof_session = proof_session::fetch(sessions, id).await?;
+    if proof_session.status != ProofSessionStatus::Completed {
+        return Err("Only completed proof sessions can be exported.".into());
+    }
+    let receipt_cid = proof_session
+        .receipt_cid
+        .clone()
+        .ok_or("Missing receipt.")?;
+    let image_id = proof_session.image_id.clone().ok_or("Missing image ID.")?;
+
+    // Every file of the image as stored, so the importer can recompute its CID
+    let (_, files) = list_manifest_from_ipfs(&proof_session.image_cid).await?;
+    let mut entries: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for (path, cid) in &files {
+        entries.insert(file_entry(path), download_from_ipfs(cid).await?);
+    }
+    entries.insert(
+        RECEIPT_ENTRY.into(),
+        download_from_ipfs(&receipt_cid).await?,
+    );
+    let session = BundleSession {
+        session_id: proof_session.session_id,
+        image_cid: proof_session.image_cid,
+        image_id,
+        method: proof_session.method,
+        arguments: proof_session.arguments,
+        result_type: proof_session.result_type,
+        result: proof_session.result,
+        created_at: proof_session.created_at.0,
+        completed_at: proof_session
+            .completed_at
+            .map(|completed_at| completed_at.0),
+    };
+    entries.insert(SESSION_ENTRY.into(), serde_json::to_vec_pretty(&session)?);
+
+    let index = BundleIndex {
+        format_version: BUNDLE_FORMAT_VERSION,
+        session_id: session.session_id.clone(),
+        image_cid: session.image_cid.clone(),
+        image_id: session.image_id.clone(),
+        files: entries
+            .iter()
+            .map(|(path, data)| (path.clone(), sha256_hex(data)))
+            .collect(),
+    };
+    let index = serde_json::to_vec_pretty(&index)?;
+    let signature = sign_index(&key_pair, &index);
+    entries.insert(BUNDLE_INDEX.into(), index);
+    entries.insert(
+        BUNDLE_SIGNATURE.into(),
+        serde_json::to_vec_pretty(&signature)?,
+    );
+
+    write_archive(&entries)
+}
+
+/// Verifies a bundle produced by [`export`] and inserts its session.
+///
+/// The index must be signed by a trusted key. The image CID is recomputed from the bundled
+/// files and the receipt is checked against the image ID of the bundled ELF, so neither the
+/// recorded image nor the recorded result have to be trusted.
+pub async fn import(
+    sessions: &SharedSessionRepository,
+    bundle: Vec<u8>,
+    api_key_id: Option<&String>,
+) -> Result<ProofSessionRecord, Box<dyn Error>> {
+    // Decompressing is CPU bound, so it runs off the async runtime like the proof checks below
+    let max_unpacked_bytes = config().limits.max_bundle_unpacked_bytes;
+    let entries = task::spawn_blocking(move || read_archive(&bundle, max_unpacked_bytes))
+        .await?
+        .map_err(|err| -> Box<dyn Error> { err })?;
+    let entry = |path: &str| -> Result<&Vec<u8>, Box<dyn Error>> {
+        entries
+            .get(path)
+            .ok_or_else(|| format!("Bundle is missing {}.", path).into())
+    };
+
+    let signature: BundleSignature = serde_json::from_slice(entry(BUNDLE_SIGNATURE)?)?;
+    verify_index(entry(BUNDLE_INDEX)?, &signature, &trusted_keys()?)?;
+    let index: BundleIndex = serde_json::from_slice(entry(BUNDLE_INDEX)?)?;
+    if index.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(format!("Unsupported bundle version {}.", index.format_version).into());
+    }
+    for (path, checksum) in &index.files {
+        if &sha256_hex(entry(path)?) != checksum {
+            return Err(format!("Checksum mismatch for {}.", path).into());
+        }
+    }
+
+    let exported: BundleSession = serde_json::from_slice(entry(SESSION_ENTRY)?)?;
+    if exported.session_id != index.session_id || exported.image_cid != index.image_cid {
+        return Err("Bundle index does not match the session.".into());
+    }
+
+    // Only files listed in the index count, an unlisted entry has no checksum to trust
+    let image_files: Vec<(&str, &[u8])> = index
+        .files
+        .keys()
+        .filter_map(|path| Some((path.strip_prefix(FILES_DIR)?, entries.get(path)?.as_slice())))
+        .collect();
+    if directory_cid(&image_files)?.to_string() != exported.image_cid {
+        return Err("Bundled files do not match the recorded image CID.".into());
+    }
+    let manifest: Manifest = serde_json::from_slice(entry(&file_entry(MANIFEST_FILE))?)?;
+    if proof_session::fetch(sessions, &exported.session_id)
+        .await
+        .is_ok()
+    {
+        return Err("Proof session already exists.".into());
+    }
+
+    // Recompute everything the receipt commits to from the bundled files
+    let elf_file = entry(&file_entry(&manifest.elf_path))?.clone();
+    let wasm_file = match &manifest.wasm_path {
+        Some(wasm_path) => Some(entry(&file_entry(wasm_path))?.as_slice()),
+        None => None,
+    };
+    let receipt_data = entry(RECEIPT_ENTRY)?;
+    let receipt: Receipt = bincode::deserialize(receipt_data)?;
+
+    // Computing the image ID and verifying the receipt take long enough to stall the runtime
+    let (image_id, receipt) = task::spawn_blocking(
+        move || -> Result<(String, Receipt), Box<dyn Error + Send + Sync>> {
+            let image_id = compute_image_id(&elf_file)?;
+            receipt
+                .verify(Digest::from_hex(&image_id)?)
+                .map_err(|err| format!("Receipt verification failed: {:?}", err))?;
+            Ok((image_id, receipt))
+        },
+    )
+    .await?
+    .map_err(|err| -> Box<dyn Error> { err })?;
+    if !image_id.eq_ignore_ascii_case(&manifest.elf_id)
+        || !image_id.eq_ignore_ascii_case(&exported.image_id)
+    {
+        return Err("ELF does not match the recorded image ID.".into());
+    }
+
+    let result = journal_result(
+        &manifest,
+        wasm_file,
+        &exported.arguments,
+        &exported.result_type,
+        &receipt.journal,
+    )
+    .map_err(|err| -> Box<dyn Error> { err })?;
+    let receipt_metadata = receipt
+        .get_metadata()
+        .map_err(|err| format!("Invalid receipt metadata: {:?}", err))?;
+
+    // Store the receipt in this environment's storage, so verification works without the origin
+    let file_name = format!("{}_receipt.bin", exported.session_id);
+    let part = Part::bytes(receipt_data.clone())
+        .file_name(file_name.clone())
+        .mime_str("application/bincode")?;
+    let receipt_cid = upload_to_ipfs(&file_name, part).await?;
+
+    let record = sessions
+        .create(
+            ProofSession {
+                session_id: &exported.session_id,
+                batch_id: None,
+                api_key_id,
+                is_wasm: manifest.wasm_path.is_some(),
+                pinned: false,
+                image_id: Some(&image_id),
+                image_cid: &exported.image_cid,
+                receipt_cid: Some(&receipt_cid),
+                receipt_metadata: Some(&receipt_metadata),
+                status: ProofSessionStatus::Completed,
+                argument_type: &manifest.argument_type,
+                method: &manifest.method,
+                arguments: &exported.arguments,
+                result_type: &exported.result_type,
+                result: Some(&result),
+                created_at: Datetime::from(exported.created_at),
+                completed_at: Some(
+                    exported
+                        .completed_at
+                        .map_or_else(Datetime::default, Datetime::from),
+                ),
+            },
+            None,
+        )
+        .await?;
+
+    info!(session_id = %record.session_id, %image_id, "Imported proof session");
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn test_index_signed_by_a_trusted_key_verifies() {
+        let key_pair = signing_key(SEED).unwrap();
+        let signature = sign_index(&key_pair, b"index");
+
+        assert!(verify_index(b"index", &signature, &[signature.public_key.clone()]).is_ok());
+        assert!(verify_index(b"tampered", &signature, &[signature.public_key.clone()]).is_err());
+    }
+
+    #[test]
+    fn test_index_signed_by_an_untrusted_key_is_rejected() {
+        let signature = sign_index(&signing_key(SEED).unwrap(), b"index");
+        let other_key = hex::encode(signing_key(&"01".repeat(32)).unwrap().public_key());
+
+        assert!(verify_index(b"index", &signature, &[other_key]).is_err());
+        assert!(verify_index(b"index", &signature, &[]).is_err());
+    }
+
+    #[test]
+    fn test_archive_larger_than_the_limit_is_rejected() {
+        let entries = BTreeMap::from([("big".to_string(), vec![0u8; 64 * 1024])]);
+        let bundle = write_archive(&entries).unwrap();
+
+        assert!(read_archive(&bundle, 1024 * 1024).is_ok());
+        assert!(read_archive(&bundle, 32 * 1024).is_err());
+    }
+}
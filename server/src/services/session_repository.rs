@@ -0,0 +1,864 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use surrealdb::sql::Datetime;
+
+use crate::{
+    db::Db,
+    services::{
+        api_key::SessionQuota,
+        proof_batch::{ProofBatch, ProofBatchCreationUpdate, ProofBatchRecord},
+        proof_session::{
+            ProofSession, ProofSessionCompleteRecord, ProofSessionFilter, ProofSessionRecord,
+            ProofSessionSortOrder, ProofSessionStatus,
+        },
+    },
+};
+
+pub const SESSION: &str = "session";
+pub const BATCH: &str = "batch";
+pub const API_KEY: &str = "api_key";
+
+pub type RepositoryResult<T> = Result<T, Box<dyn Error>>;
+
+/// Returned by `create` when a session would exceed the quota of its API key.
+#[derive(Debug)]
+pub struct QuotaExceeded;
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("API key quota exceeded")
+    }
+}
+
+impl Error for QuotaExceeded {}
+
+/// Repository shared with the route handlers through axum state.
+pub type SharedSessionRepository = Arc<dyn SessionRepository>;
+
+/// Persistence of proof sessions and the batches grouping them, so the session logic
+/// does not depend on a database.
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    /// Inserts a session. With a quota, the sessions of its API key are counted in the same
+    /// transaction as the insert, failing with [`QuotaExceeded`] when the session doesn't fit.
+    async fn create(
+        &self,
+        session: ProofSession<'_>,
+        quota: Option<SessionQuota>,
+    ) -> RepositoryResult<ProofSessionRecord>;
+
+    async fn fetch(&self, session_id: &str) -> RepositoryResult<ProofSessionRecord>;
+
+    async fn list(
+        &self,
+        image_cid: Option<&str>,
+        filter: &ProofSessionFilter,
+    ) -> RepositoryResult<Vec<ProofSessionRecord>>;
+
+    /// Every session of a batch, oldest first and without paging.
+    async fn list_by_batch(&self, batch_id: &str) -> RepositoryResult<Vec<ProofSessionRecord>>;
+
+    /// Sets the status of a session, marking it completed when the status is final.
+    async fn update_status(
+        &self,
+        record_id: &str,
+        status: ProofSessionStatus,
+        reason: Option<&str>,
+    ) -> RepositoryResult<()>;
+
+    async fn complete(
+        &self,
+        record_id: &str,
+        completion: ProofSessionCompleteRecord,
+    ) -> RepositoryResult<()>;
+
+    async fn set_pinned(
+        &self,
+        session_id: &str,
+        pinned: bool,
+    ) -> RepositoryResult<ProofSessionRecord>;
+
+    /// A page of the finished sessions that are not pinned, grouped by image and newest
+    /// first. With `created_before`, only sessions created before it are listed.
+    async fn list_collectable(
+        &self,
+        created_before: Option<&Datetime>,
+        limit: usize,
+        offset: usize,
+    ) -> RepositoryResult<Vec<ProofSessionRecord>>;
+
+    /// Deletes sessions by session ID, returning how many were deleted.
+    async fn delete(&self, session_ids: &[String]) -> RepositoryResult<usize>;
+
+    /// Newest sessions whose IDs, image CID or receipt CID start with the query, or whose
+    /// method contains it. IDs and methods are matched case-insensitively, CIDs are not.
+    async fn search(&self, query: &str, limit: usize) -> RepositoryResult<Vec<ProofSessionRecord>>;
+
+    /// Newest sessions whose IDs, image CID or receipt CID equal the query, matched like
+    /// `search`.
+    async fn search_exact(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> RepositoryResult<Vec<ProofSessionRecord>>;
+
+    async fn create_batch(&self, batch: ProofBatch<'_>) -> RepositoryResult<ProofBatchRecord>;
+
+    /// Records which sessions of a batch were created, and whether all of them were.
+    async fn update_batch(
+        &self,
+        batch_id: &str,
+        update: ProofBatchCreationUpdate<'_>,
+    ) -> RepositoryResult<ProofBatchRecord>;
+
+    async fn fetch_batch(&self, batch_id: &str) -> RepositoryResult<ProofBatchRecord>;
+}
+
+const ACTIVE_STATUSES: [ProofSessionStatus; 2] = [
+    ProofSessionStatus::Preparing,
+    ProofSessionStatus::InProgress,
+];
+
+const FINISHED_STATUSES: [ProofSessionStatus; 4] = [
+    ProofSessionStatus::Completed,
+    ProofSessionStatus::TimedOut,
+    ProofSessionStatus::Failed,
+    ProofSessionStatus::Cancelled,
+];
+
+#[derive(Debug, Serialize)]
+struct ProofSessionStatusRecord<'a> {
+    status: ProofSessionStatus,
+    status_reason: Option<&'a str>,
+    completed_at: Option<Datetime>,
+}
+
+impl<'a> ProofSessionStatusRecord<'a> {
+    fn new(status: ProofSessionStatus, reason: Option<&'a str>) -> Self {
+        let completed_at = status.is_finished().then(Datetime::default);
+        ProofSessionStatusRecord {
+            status,
+            status_reason: reason,
+            completed_at,
+        }
+    }
+}
+
+/// Stores sessions in the `session` table of the given database.
+#[derive(Debug, Clone)]
+pub struct SurrealSessionRepository {
+    db: Db,
+}
+
+impl SurrealSessionRepository {
+    pub fn new(db: Db) -> Self {
+        SurrealSessionRepository { db }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SurrealSessionRepository {
+    async fn create(
+        &self,
+        session: ProofSession<'_>,
+        quota: Option<SessionQuota>,
+    ) -> RepositoryResult<ProofSessionRecord> {
+        let (Some(quota), Some(api_key_id)) = (quota, session.api_key_id) else {
+            let record: ProofSessionRecord = self.db.create(SESSION).content(session).await?;
+            return Ok(record);
+        };
+
+        // Writing the key record makes concurrent inserts for the same key conflict, so
+        // neither can count the sessions before the other's insert is committed
+        let mut response = self
+            .db
+            .query("BEGIN TRANSACTION")
+            .query(
+                "UPDATE type::thing($api_key_table, $api_key_id) \
+                    SET quota_checked_at = time::now()",
+            )
+            .query(
+                "IF count((SELECT id FROM type::table($table) \
+                        WHERE api_key_id = $api_key_id AND created_at > time::now() - 1d)) \
+                        < $daily_quota \
+                    AND count((SELECT id FROM type::table($table) \
+                        WHERE api_key_id = $api_key_id AND status INSIDE $active_statuses)) \
+                        < $max_concurrent_sessions \
+                THEN (CREATE type::table($table) CONTENT $session) \
+                ELSE NONE END",
+            )
+            .query("COMMIT TRANSACTION")
+            .bind(("table", SESSION))
+            .bind(("api_key_table", API_KEY))
+            .bind(("api_key_id", api_key_id))
+            .bind(("daily_quota", quota.daily_quota))
+            .bind(("max_concurrent_sessions", quota.max_concurrent_sessions))
+            .bind(("active_statuses", ACTIVE_STATUSES))
+            .bind(("session", session))
+            .await?;
+
+        let record: Option<ProofSessionRecord> = response.take(1)?;
+
+        record.ok_or_else(|| QuotaExceeded.into())
+    }
+
+    async fn fetch(&self, session_id: &str) -> RepositoryResult<ProofSessionRecord> {
+        let mut response = self
+            .db
+            .query("SELECT * FROM type::table($table) WHERE session_id = $session_id")
+            .bind(("table", SESSION))
+            .bind(("session_id", session_id))
+            .await?;
+
+        let record: Option<ProofSessionRecord> = response.take(0)?;
+
+        if let Some(record) = record {
+            Ok(record)
+        } else {
+            Err("Error::RowNotFound".into())
+        }
+    }
+
+    async fn list(
+        &self,
+        image_cid: Option<&str>,
+        filter: &ProofSessionFilter,
+    ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+        let mut conditions: Vec<&str> = Vec::new();
+        if image_cid.is_some() {
+            conditions.push("image_cid = $image_cid");
+        }
+        if filter.status.is_some() {
+            conditions.push("status = $status");
+        }
+        if filter.is_wasm.is_some() {
+            conditions.push("is_wasm = $is_wasm");
+        }
+        if filter.created_after.is_some() {
+            conditions.push("created_at >= type::datetime($created_after)");
+        }
+        if filter.created_before.is_some() {
+            conditions.push("created_at < type::datetime($created_before)");
+        }
+
+        let mut query = String::from("SELECT * FROM type::table($table)");
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+        query.push_str(match filter.order {
+            ProofSessionSortOrder::Asc => " ORDER BY created_at ASC",
+            ProofSessionSortOrder::Desc => " ORDER BY created_at DESC",
+        });
+        query.push_str(" LIMIT $limit START $offset");
+
+        let (limit, offset) = filter.page();
+
+        let mut response = self
+            .db
+            .query(query)
+            .bind(("table", SESSION))
+            .bind(("image_cid", image_cid))
+            .bind(("status", &filter.status))
+            .bind(("is_wasm", filter.is_wasm))
+            .bind(("created_after", &filter.created_after))
+            .bind(("created_before", &filter.created_before))
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?;
+
+        let records: Vec<ProofSessionRecord> = response.take(0)?;
+
+        Ok(records)
+    }
+
+    async fn list_by_batch(&self, batch_id: &str) -> RepositoryResult<Vec<ProofSessionRecord>> {
+        let mut response = self
+            .db
+            .query(
+                "SELECT * FROM type::table($table) WHERE batch_id = $batch_id \
+                    ORDER BY created_at ASC",
+            )
+            .bind(("table", SESSION))
+            .bind(("batch_id", batch_id))
+            .await?;
+
+        let records: Vec<ProofSessionRecord> = response.take(0)?;
+
+        Ok(records)
+    }
+
+    async fn update_status(
+        &self,
+        record_id: &str,
+        status: ProofSessionStatus,
+        reason: Option<&str>,
+    ) -> RepositoryResult<()> {
+        let _: ProofSessionRecord = self
+            .db
+            .update((SESSION, record_id))
+            .merge(ProofSessionStatusRecord::new(status, reason))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn complete(
+        &self,
+        record_id: &str,
+        completion: ProofSessionCompleteRecord,
+    ) -> RepositoryResult<()> {
+        let _: ProofSessionRecord = self
+            .db
+            .update((SESSION, record_id))
+            .merge(completion)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_pinned(
+        &self,
+        session_id: &str,
+        pinned: bool,
+    ) -> RepositoryResult<ProofSessionRecord> {
+        let mut response = self
+            .db
+            .query("UPDATE type::table($table) SET pinned = $pinned WHERE session_id = $session_id")
+            .bind(("table", SESSION))
+            .bind(("session_id", session_id))
+            .bind(("pinned", pinned))
+            .await?;
+
+        let record: Option<ProofSessionRecord> = response.take(0)?;
+
+        if let Some(record) = record {
+            Ok(record)
+        } else {
+            Err("Error::RowNotFound".into())
+        }
+    }
+
+    async fn list_collectable(
+        &self,
+        created_before: Option<&Datetime>,
+        limit: usize,
+        offset: usize,
+    ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+        let mut query = String::from(
+            "SELECT * FROM type::table($table) \
+                WHERE pinned != true AND status INSIDE $finished_statuses",
+        );
+        if created_before.is_some() {
+            query.push_str(" AND created_at < $created_before");
+        }
+        query.push_str(" ORDER BY image_cid ASC, created_at DESC LIMIT $limit START $offset");
+
+        let mut response = self
+            .db
+            .query(query)
+            .bind(("table", SESSION))
+            .bind(("finished_statuses", FINISHED_STATUSES))
+            .bind(("created_before", created_before))
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?;
+
+        let records: Vec<ProofSessionRecord> = response.take(0)?;
+
+        Ok(records)
+    }
+
+    async fn delete(&self, session_ids: &[String]) -> RepositoryResult<usize> {
+        let mut response = self
+            .db
+            .query("DELETE type::table($table) WHERE session_id INSIDE $session_ids RETURN BEFORE")
+            .bind(("table", SESSION))
+            .bind(("session_ids", session_ids))
+            .await?;
+
+        let records: Vec<ProofSessionRecord> = response.take(0)?;
+
+        Ok(records.len())
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> RepositoryResult<Vec<ProofSessionRecord>> {
+        let mut response = self
+            .db
+            .query(
+                "SELECT * FROM type::table($table) WHERE \
+                    string::startsWith(session_id, $q_lower) \
+                    OR string::startsWith(image_cid, $q) \
+                    OR (image_id != NONE AND string::startsWith(image_id, $q_lower)) \
+                    OR (receipt_cid != NONE AND string::startsWith(receipt_cid, $q)) \
+                    OR string::lowercase(method) CONTAINS $q_lower \
+                ORDER BY created_at DESC LIMIT $limit",
+            )
+            .bind(("table", SESSION))
+            .bind(("q", query))
+            .bind(("q_lower", query.to_lowercase()))
+            .bind(("limit", limit))
+            .await?;
+
+        let records: Vec<ProofSessionRecord> = response.take(0)?;
+
+        Ok(records)
+    }
+
+    async fn search_exact(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+        let mut response = self
+            .db
+            .query(
+                "SELECT * FROM type::table($table) WHERE \
+                    session_id = $q_lower OR image_cid = $q \
+                    OR image_id = $q_lower OR receipt_cid = $q \
+                ORDER BY created_at DESC LIMIT $limit",
+            )
+            .bind(("table", SESSION))
+            .bind(("q", query))
+            .bind(("q_lower", query.to_lowercase()))
+            .bind(("limit", limit))
+            .await?;
+
+        let records: Vec<ProofSessionRecord> = response.take(0)?;
+
+        Ok(records)
+    }
+
+    async fn create_batch(&self, batch: ProofBatch<'_>) -> RepositoryResult<ProofBatchRecord> {
+        let record: ProofBatchRecord = self.db.create(BATCH).content(batch).await?;
+
+        Ok(record)
+    }
+
+    async fn update_batch(
+        &self,
+        batch_id: &str,
+        update: ProofBatchCreationUpdate<'_>,
+    ) -> RepositoryResult<ProofBatchRecord> {
+        let mut response = self
+            .db
+            .query("UPDATE type::table($table) MERGE $update WHERE batch_id = $batch_id")
+            .bind(("table", BATCH))
+            .bind(("batch_id", batch_id))
+            .bind(("update", update))
+            .await?;
+
+        let record: Option<ProofBatchRecord> = response.take(0)?;
+
+        if let Some(record) = record {
+            Ok(record)
+        } else {
+            Err("Error::RowNotFound".into())
+        }
+    }
+
+    async fn fetch_batch(&self, batch_id: &str) -> RepositoryResult<ProofBatchRecord> {
+        let mut response = self
+            .db
+            .query("SELECT * FROM type::table($table) WHERE batch_id = $batch_id")
+            .bind(("table", BATCH))
+            .bind(("batch_id", batch_id))
+            .await?;
+
+        let record: Option<ProofBatchRecord> = response.take(0)?;
+
+        if let Some(record) = record {
+            Ok(record)
+        } else {
+            Err("Error::RowNotFound".into())
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod in_memory {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use chrono::{DateTime, Duration, Utc};
+    use surrealdb::sql::{Id, Thing};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::services::{api_key::ApiKeyRecord, proof_session::DynType};
+
+    /// Keeps sessions in memory, for tests of the session logic without a database.
+    #[derive(Debug, Default)]
+    pub struct InMemorySessionRepository {
+        records: Mutex<HashMap<String, ProofSessionRecord>>,
+        batches: Mutex<HashMap<String, ProofBatchRecord>>,
+    }
+
+    fn parse_filter_datetime(value: &Option<String>) -> RepositoryResult<Option<DateTime<Utc>>> {
+        match value {
+            Some(value) => Ok(Some(
+                DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    #[async_trait]
+    impl SessionRepository for InMemorySessionRepository {
+        async fn create(
+            &self,
+            session: ProofSession<'_>,
+            quota: Option<SessionQuota>,
+        ) -> RepositoryResult<ProofSessionRecord> {
+            // Counting and inserting under the same lock is what the transaction does
+            let mut records = self.records.lock().unwrap();
+            if let (Some(quota), Some(api_key_id)) = (quota, session.api_key_id) {
+                let day_ago = Utc::now() - Duration::days(1);
+                let key_records = records
+                    .values()
+                    .filter(|r| r.api_key_id.as_ref() == Some(api_key_id));
+                let sessions_today = key_records
+                    .clone()
+                    .filter(|r| r.created_at.0 > day_ago)
+                    .count();
+                let active_sessions = key_records.filter(|r| !r.status.is_finished()).count();
+                if sessions_today >= quota.daily_quota
+                    || active_sessions >= quota.max_concurrent_sessions
+                {
+                    return Err(QuotaExceeded.into());
+                }
+            }
+
+            let record = ProofSessionRecord {
+                id: Thing {
+                    tb: SESSION.into(),
+                    id: Id::String(Uuid::new_v4().simple().to_string()),
+                },
+                session_id: session.session_id.clone(),
+                batch_id: session.batch_id.cloned(),
+                api_key_id: session.api_key_id.cloned(),
+                is_wasm: session.is_wasm,
+                pinned: session.pinned,
+                image_id: session.image_id.cloned(),
+                image_cid: session.image_cid.clone(),
+                receipt_cid: session.receipt_cid.cloned(),
+                receipt_metadata: session.receipt_metadata.cloned(),
+                result: session.result.cloned(),
+                status: session.status,
+                method: session.method.clone(),
+                argument_type: session.argument_type.clone(),
+                arguments: session.arguments.clone(),
+                result_type: session.result_type.clone(),
+                created_at: session.created_at,
+                completed_at: session.completed_at,
+                status_reason: None,
+            };
+
+            records.insert(record.record_id(), record.clone());
+
+            Ok(record)
+        }
+
+        async fn fetch(&self, session_id: &str) -> RepositoryResult<ProofSessionRecord> {
+            let records = self.records.lock().unwrap();
+            match records.values().find(|r| r.session_id == session_id) {
+                Some(record) => Ok(record.clone()),
+                None => Err("Error::RowNotFound".into()),
+            }
+        }
+
+        async fn list(
+            &self,
+            image_cid: Option<&str>,
+            filter: &ProofSessionFilter,
+        ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+            let created_after = parse_filter_datetime(&filter.created_after)?;
+            let created_before = parse_filter_datetime(&filter.created_before)?;
+
+            let mut records: Vec<ProofSessionRecord> = self
+                .records
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|r| image_cid.map_or(true, |cid| r.image_cid == cid))
+                .filter(|r| filter.status.as_ref().map_or(true, |s| &r.status == s))
+                .filter(|r| filter.is_wasm.map_or(true, |is_wasm| r.is_wasm == is_wasm))
+                .filter(|r| created_after.map_or(true, |after| r.created_at.0 >= after))
+                .filter(|r| created_before.map_or(true, |before| r.created_at.0 < before))
+                .cloned()
+                .collect();
+
+            records.sort_by(|a, b| match filter.order {
+                ProofSessionSortOrder::Asc => a.created_at.cmp(&b.created_at),
+                ProofSessionSortOrder::Desc => b.created_at.cmp(&a.created_at),
+            });
+
+            let (limit, offset) = filter.page();
+
+            Ok(records.into_iter().skip(offset).take(limit).collect())
+        }
+
+        async fn list_by_batch(&self, batch_id: &str) -> RepositoryResult<Vec<ProofSessionRecord>> {
+            let mut records: Vec<ProofSessionRecord> = self
+                .records
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|r| r.batch_id.as_deref() == Some(batch_id))
+                .cloned()
+                .collect();
+            records.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+            Ok(records)
+        }
+
+        async fn update_status(
+            &self,
+            record_id: &str,
+            status: ProofSessionStatus,
+            reason: Option<&str>,
+        ) -> RepositoryResult<()> {
+            let update = ProofSessionStatusRecord::new(status, reason);
+            let mut records = self.records.lock().unwrap();
+            let record = records.get_mut(record_id).ok_or("Error::RowNotFound")?;
+
+            record.status = update.status;
+            record.status_reason = update.status_reason.map(String::from);
+            record.completed_at = update.completed_at;
+
+            Ok(())
+        }
+
+        async fn complete(
+            &self,
+            record_id: &str,
+            completion: ProofSessionCompleteRecord,
+        ) -> RepositoryResult<()> {
+            let mut records = self.records.lock().unwrap();
+            let record = records.get_mut(record_id).ok_or("Error::RowNotFound")?;
+
+            record.status = completion.status;
+            record.completed_at = Some(completion.completed_at);
+            record.image_id = completion.image_id;
+            record.receipt_cid = completion.receipt_cid;
+            record.receipt_metadata = completion.receipt_metadata;
+            record.result = completion.result;
+            record.status_reason = completion.status_reason;
+
+            Ok(())
+        }
+
+        async fn set_pinned(
+            &self,
+            session_id: &str,
+            pinned: bool,
+        ) -> RepositoryResult<ProofSessionRecord> {
+            let mut records = self.records.lock().unwrap();
+            let record = records
+                .values_mut()
+                .find(|r| r.session_id == session_id)
+                .ok_or("Error::RowNotFound")?;
+
+            record.pinned = pinned;
+
+            Ok(record.clone())
+        }
+
+        async fn list_collectable(
+            &self,
+            created_before: Option<&Datetime>,
+            limit: usize,
+            offset: usize,
+        ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+            let mut records: Vec<ProofSessionRecord> = self
+                .records
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|r| !r.pinned && r.status.is_finished())
+                .filter(|r| created_before.map_or(true, |before| r.created_at < *before))
+                .cloned()
+                .collect();
+            records.sort_by(|a, b| {
+                a.image_cid
+                    .cmp(&b.image_cid)
+                    .then_with(|| b.created_at.cmp(&a.created_at))
+            });
+
+            Ok(records.into_iter().skip(offset).take(limit).collect())
+        }
+
+        async fn delete(&self, session_ids: &[String]) -> RepositoryResult<usize> {
+            let mut records = self.records.lock().unwrap();
+            let before = records.len();
+            records.retain(|_, r| !session_ids.contains(&r.session_id));
+
+            Ok(before - records.len())
+        }
+
+        async fn search(
+            &self,
+            query: &str,
+            limit: usize,
+        ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+            let query_lower = query.to_lowercase();
+            let starts_with = |value: &Option<String>, prefix: &str| {
+                value
+                    .as_ref()
+                    .map_or(false, |value| value.starts_with(prefix))
+            };
+
+            let mut records: Vec<ProofSessionRecord> = self
+                .records
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|r| {
+                    r.session_id.starts_with(&query_lower)
+                        || r.image_cid.starts_with(query)
+                        || starts_with(&r.image_id, &query_lower)
+                        || starts_with(&r.receipt_cid, query)
+                        || r.method.to_lowercase().contains(&query_lower)
+                })
+                .cloned()
+                .collect();
+            records.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            records.truncate(limit);
+
+            Ok(records)
+        }
+
+        async fn search_exact(
+            &self,
+            query: &str,
+            limit: usize,
+        ) -> RepositoryResult<Vec<ProofSessionRecord>> {
+            let query_lower = query.to_lowercase();
+
+            let mut records: Vec<ProofSessionRecord> = self
+                .records
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|r| {
+                    r.session_id == query_lower
+                        || r.image_cid == query
+                        || r.image_id.as_deref() == Some(query_lower.as_str())
+                        || r.receipt_cid.as_deref() == Some(query)
+                })
+                .cloned()
+                .collect();
+            records.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            records.truncate(limit);
+
+            Ok(records)
+        }
+
+        async fn create_batch(&self, batch: ProofBatch<'_>) -> RepositoryResult<ProofBatchRecord> {
+            let record = ProofBatchRecord {
+                id: Thing {
+                    tb: BATCH.into(),
+                    id: Id::String(Uuid::new_v4().simple().to_string()),
+                },
+                batch_id: batch.batch_id.clone(),
+                image_cid: batch.image_cid.clone(),
+                session_ids: batch.session_ids.clone(),
+                creation: batch.creation,
+                creation_error: None,
+                created_at: batch.created_at,
+            };
+
+            self.batches
+                .lock()
+                .unwrap()
+                .insert(record.batch_id.clone(), record.clone());
+
+            Ok(record)
+        }
+
+        async fn update_batch(
+            &self,
+            batch_id: &str,
+            update: ProofBatchCreationUpdate<'_>,
+        ) -> RepositoryResult<ProofBatchRecord> {
+            let mut batches = self.batches.lock().unwrap();
+            let record = batches.get_mut(batch_id).ok_or("Error::RowNotFound")?;
+
+            record.session_ids = update.session_ids.clone();
+            record.creation = update.creation;
+            record.creation_error = update.creation_error.map(String::from);
+
+            Ok(record.clone())
+        }
+
+        async fn fetch_batch(&self, batch_id: &str) -> RepositoryResult<ProofBatchRecord> {
+            match self.batches.lock().unwrap().get(batch_id) {
+                Some(record) => Ok(record.clone()),
+                None => Err("Error::RowNotFound".into()),
+            }
+        }
+    }
+
+    /// Inserts a session of `image_cid` with the given status, created `age_days` ago.
+    pub(crate) async fn create_test_session(
+        sessions: &SharedSessionRepository,
+        image_cid: &str,
+        batch_id: Option<&str>,
+        status: ProofSessionStatus,
+        age_days: i64,
+    ) -> ProofSessionRecord {
+        insert_test_session(sessions, image_cid, batch_id, None, status, age_days)
+            .await
+            .unwrap()
+    }
+
+    /// Inserts a session created with `api_key` now, checked against the key's quota.
+    pub(crate) async fn create_test_session_for_key(
+        sessions: &SharedSessionRepository,
+        api_key: &ApiKeyRecord,
+        status: ProofSessionStatus,
+    ) -> RepositoryResult<ProofSessionRecord> {
+        insert_test_session(sessions, "bafyimage", None, Some(api_key), status, 0).await
+    }
+
+    async fn insert_test_session(
+        sessions: &SharedSessionRepository,
+        image_cid: &str,
+        batch_id: Option<&str>,
+        api_key: Option<&ApiKeyRecord>,
+        status: ProofSessionStatus,
+        age_days: i64,
+    ) -> RepositoryResult<ProofSessionRecord> {
+        let session_id = Uuid::new_v4().to_string();
+        let image_cid = image_cid.to_string();
+        let batch_id = batch_id.map(String::from);
+        let api_key_id = api_key.map(ApiKeyRecord::key_id);
+        let completed_at = status.is_finished().then(Datetime::default);
+
+        sessions
+            .create(
+                ProofSession {
+                    session_id: &session_id,
+                    batch_id: batch_id.as_ref(),
+                    api_key_id: api_key_id.as_ref(),
+                    is_wasm: true,
+                    pinned: false,
+                    image_id: None,
+                    image_cid: &image_cid,
+                    receipt_cid: None,
+                    receipt_metadata: None,
+                    status,
+                    argument_type: &vec![DynType::I32],
+                    method: &"zkmain".to_string(),
+                    arguments: &Vec::new(),
+                    result_type: &DynType::I32,
+                    result: None,
+                    created_at: Datetime::from(Utc::now() - Duration::days(age_days)),
+                    completed_at,
+                },
+                api_key.map(ApiKeyRecord::quota),
+            )
+            .await
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use tokio::{signal, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::{
+    metrics,
+    services::{
+        proof_session::{self, ProofSessionStatus},
+        session_repository::SharedSessionRepository,
+    },
+};
+
+const SHUTDOWN_REASON: &str = "Server shut down before the proof completed.";
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+static ACCEPTING_SESSIONS: AtomicBool = AtomicBool::new(true);
+
+struct RunningSession {
+    image_cid: String,
+    started_at: Instant,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Proof sessions that have been spawned but not finished, keyed by record ID.
+static RUNNING_SESSIONS: Lazy<Mutex<HashMap<String, RunningSession>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn is_accepting_sessions() -> bool {
+    ACCEPTING_SESSIONS.load(Ordering::SeqCst)
+}
+
+pub fn running_sessions() -> usize {
+    RUNNING_SESSIONS.lock().unwrap().len()
+}
+
+/// A tracked session, untracked when dropped so a session task that panics or returns
+/// early never holds up draining.
+pub(crate) struct TrackedSession {
+    record_id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TrackedSession {
+    /// The flag set when the session is cancelled at shutdown. Proofs run on blocking
+    /// threads that can't be aborted, so the session has to check it and stop on its own.
+    pub fn cancelled(&self) -> &Arc<AtomicBool> {
+        &self.cancelled
+    }
+
+    /// Returns whether the session was still tracked. Draining at shutdown untracks
+    /// sessions too, and whoever untracks a session records it as finished.
+    pub fn untrack(self) -> bool {
+        untrack_session(&self.record_id)
+    }
+}
+
+impl Drop for TrackedSession {
+    fn drop(&mut self) {
+        untrack_session(&self.record_id);
+    }
+}
+
+pub(crate) fn track_session(record_id: &String, image_cid: &String) -> TrackedSession {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    RUNNING_SESSIONS.lock().unwrap().insert(
+        record_id.to_string(),
+        RunningSession {
+            image_cid: image_cid.to_string(),
+            started_at: Instant::now(),
+            cancelled: cancelled.clone(),
+        },
+    );
+
+    TrackedSession {
+        record_id: record_id.to_string(),
+        cancelled,
+    }
+}
+
+fn untrack_session(record_id: &String) -> bool {
+    RUNNING_SESSIONS.lock().unwrap().remove(record_id).is_some()
+}
+
+/// Resolves on SIGINT or SIGTERM, after which no new proof sessions are accepted.
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    ACCEPTING_SESSIONS.store(false, Ordering::SeqCst);
+    info!(
+        running_sessions = running_sessions(),
+        "Shutdown requested, no longer accepting proof sessions"
+    );
+}
+
+/// Waits up to `grace_period` for running proofs, then cancels whatever is left.
+/// Returns the number of sessions that had to be cancelled.
+///
+/// Cancelling only flags the sessions and marks them `Cancelled`. A proof already running
+/// on a blocking thread keeps going until it next checks the flag, or the process exits.
+pub async fn drain_sessions(sessions: &SharedSessionRepository, grace_period: Duration) -> usize {
+    let deadline = Instant::now() + grace_period;
+    while running_sessions() > 0 && Instant::now() < deadline {
+        sleep(DRAIN_POLL_INTERVAL).await;
+    }
+
+    let remaining: Vec<(String, RunningSession)> =
+        RUNNING_SESSIONS.lock().unwrap().drain().collect();
+    if remaining.is_empty() {
+        info!("All proof sessions drained");
+        return 0;
+    }
+
+    warn!(
+        remaining = remaining.len(),
+        "Grace period elapsed, cancelling running proof sessions"
+    );
+
+    let cancelled = remaining.len();
+    for (record_id, session) in remaining {
+        session.cancelled.store(true, Ordering::SeqCst);
+
+        metrics::record_session_finished(
+            &session.image_cid,
+            &ProofSessionStatus::Cancelled,
+            session.started_at.elapsed(),
+        );
+
+        if proof_session::cancel(sessions, &record_id, SHUTDOWN_REASON)
+            .await
+            .is_err()
+        {
+            error!(record_id = %record_id, "Failed to mark proof session as cancelled");
+        }
+    }
+
+    cancelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_is_untracked_once() {
+        let record_id = "untracked-once".to_string();
+        let session = track_session(&record_id, &"bafyimage".to_string());
+
+        assert!(session.untrack());
+        assert!(!untrack_session(&record_id));
+    }
+
+    #[test]
+    fn test_dropped_session_is_untracked() {
+        let record_id = "dropped".to_string();
+        let result = std::panic::catch_unwind(|| {
+            let _session = track_session(&record_id, &"bafyimage".to_string());
+            panic!("session task panicked");
+        });
+
+        assert!(result.is_err());
+        assert!(!RUNNING_SESSIONS.lock().unwrap().contains_key(&record_id));
+    }
+}
@@ -0,0 +1,110 @@
+use std::error::Error;
+
+use risc0_zkvm::{
+    serde::from_slice,
+    sha::{Digest, Impl, Sha256},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::services::proof_session::{DynType, ProofSessionArgument};
+
+/// A WASM value, typed the way WASM guests read and commit it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum WasmValue {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl WasmValue {
+    pub fn parse(argument: &ProofSessionArgument) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let value = &argument.value;
+        let parsed = match argument.arg_type {
+            DynType::Integer | DynType::I32 => value.parse().map(WasmValue::I32).ok(),
+            DynType::Float | DynType::F32 => value.parse().map(WasmValue::F32).ok(),
+            DynType::I64 => value.parse().map(WasmValue::I64).ok(),
+            DynType::F64 => value.parse().map(WasmValue::F64).ok(),
+        };
+
+        parsed.ok_or_else(|| {
+            format!(
+                "Argument {} is not a valid {}.",
+                value,
+                argument.arg_type.to_string()
+            )
+            .into()
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        match *self {
+            WasmValue::I32(value) => value.into(),
+            WasmValue::I64(value) => value.into(),
+            WasmValue::F32(value) => value.into(),
+            WasmValue::F64(value) => value.into(),
+        }
+    }
+}
+
+/// How the call ended; running out of fuel is proven rather than aborting the proof.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum WasmOutcome {
+    Returned(WasmValue),
+    OutOfFuel,
+    /// The universal guest called a function that returns something other than a single
+    /// number, with the reason
+    Unsupported(String),
+}
+
+/// What WASM guests commit: which module ran, how it was called and what it returned.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WasmJournal {
+    pub wasm_sha256: Digest,
+    pub method: String,
+    pub arguments: Vec<WasmValue>,
+    /// Bytes the module committed through the `commit` host function
+    pub committed: Vec<u8>,
+    pub result: WasmOutcome,
+}
+
+impl WasmJournal {
+    pub fn decode(journal: &[u8]) -> Result<Self, risc0_zkvm::serde::Error> {
+        from_slice(journal)
+    }
+
+    /// Fails unless the journal was produced by running exactly this module.
+    pub fn check_module(&self, wasm_file: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if *Impl::hash_bytes(wasm_file) != self.wasm_sha256 {
+            return Err("Journal WASM hash does not match the image's WASM file.".into());
+        }
+
+        Ok(())
+    }
+
+    /// Fails unless the journal records exactly the call the session asked for.
+    pub fn check_call(
+        &self,
+        method: &str,
+        arguments: &[ProofSessionArgument],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.method != method {
+            return Err(format!(
+                "Journal method {} does not match the session method {}.",
+                self.method, method
+            )
+            .into());
+        }
+
+        let expected = arguments
+            .iter()
+            .map(WasmValue::parse)
+            .collect::<Result<Vec<WasmValue>, _>>()?;
+        if self.arguments != expected {
+            return Err("Journal arguments do not match the session arguments.".into());
+        }
+
+        Ok(())
+    }
+}
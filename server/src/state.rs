@@ -0,0 +1,22 @@
+use axum::extract::FromRef;
+
+use crate::{db::Db, services::session_repository::SharedSessionRepository};
+
+/// State shared by every router; handlers extract the part they need, e.g. `State<Db>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Db,
+    pub sessions: SharedSessionRepository,
+}
+
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedSessionRepository {
+    fn from_ref(state: &AppState) -> Self {
+        state.sessions.clone()
+    }
+}
@@ -2,9 +2,12 @@ use reqwest::multipart::{Form, Part};
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::env;
 use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
 
+use crate::config::config;
+use crate::metrics::{IPFS_REQUEST_DURATION, IPFS_REQUEST_ERRORS};
 use crate::services::proof_session::Manifest;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,13 +34,40 @@ struct IPFSListResponse {
     Objects: Vec<IPFSObject>,
 }
 
+/// Records the latency and failures of an IPFS request under the given operation label.
+async fn observe_ipfs_request<T>(
+    operation: &str,
+    request: impl Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    let timer = IPFS_REQUEST_DURATION
+        .with_label_values(&[operation])
+        .start_timer();
+    let result = request.await;
+    timer.observe_duration();
+
+    if result.is_err() {
+        IPFS_REQUEST_ERRORS.with_label_values(&[operation]).inc();
+    }
+
+    result
+}
+
+/// The manifest of a package, with the CID of every file in it by name, the manifest included.
 pub async fn list_manifest_from_ipfs(
     cid: &String,
 ) -> Result<(Manifest, HashMap<String, String>), Box<dyn Error>> {
-    let base_url = "https://dweb.link/api/v0";
-    let cid_url = format!("{}/ls/{}", base_url, cid);
+    observe_ipfs_request("list", list_manifest(cid)).await
+}
+
+async fn list_manifest(
+    cid: &String,
+) -> Result<(Manifest, HashMap<String, String>), Box<dyn Error>> {
+    let config = config();
+    let cid_url = format!("{}/ls/{}", config.storage.ipfs_api_url, cid);
 
-    let client: Client = Client::builder().build()?;
+    let client: Client = Client::builder()
+        .timeout(config.storage_request_timeout())
+        .build()?;
     let response = client.get(&cid_url).send().await?;
 
     if response.status() != StatusCode::OK {
@@ -53,9 +83,8 @@ pub async fn list_manifest_from_ipfs(
         if link.Name == "manifest.json" {
             let manifest_bytes = download_from_ipfs(&link.Hash).await?;
             manifest = Some(serde_json::from_slice(&manifest_bytes)?);
-        } else {
-            files.insert(link.Name.to_string(), link.Hash.to_string());
         }
+        files.insert(link.Name.to_string(), link.Hash.to_string());
     }
 
     if let Some(manifest) = manifest {
@@ -66,40 +95,113 @@ pub async fn list_manifest_from_ipfs(
 }
 
 pub async fn download_from_ipfs(cid: &String) -> Result<Vec<u8>, Box<dyn Error>> {
-    let base_url = "https://dweb.link/api/v0";
-    let cid_url = format!("{}/cat/{}", base_url, cid);
+    observe_ipfs_request("download", download(cid)).await
+}
+
+async fn download(cid: &String) -> Result<Vec<u8>, Box<dyn Error>> {
+    let config = config();
+    let cid_url = format!("{}/cat/{}", config.storage.ipfs_api_url, cid);
 
-    let client: Client = Client::builder().build().unwrap();
-    let response = client.get(&cid_url).send().await.unwrap();
+    let client: Client = Client::builder()
+        .timeout(config.storage_request_timeout())
+        .build()?;
+    let response = client.get(&cid_url).send().await?;
 
     if response.status() != StatusCode::OK {
         return Err("Failed to download file from IPFS.".into());
     }
 
-    let content = response.bytes().await.unwrap().to_vec();
+    let content = response.bytes().await?.to_vec();
 
     Ok(content)
 }
 
 pub async fn upload_to_ipfs(file_name: &str, file: Part) -> Result<String, Box<dyn Error>> {
+    observe_ipfs_request("upload", upload(file_name, file)).await
+}
+
+async fn upload(file_name: &str, file: Part) -> Result<String, Box<dyn Error>> {
+    let config = config();
+    let token = config
+        .storage
+        .web3_storage_token
+        .as_ref()
+        .ok_or("No web3.storage token configured.")?;
+
     let mut form = Form::new();
     form = form.part("file", file);
 
-    let response = reqwest::Client::new()
-        .post("https://api.web3.storage/upload")
-        .bearer_auth(env::var("WEB3_STORAGE_TOKEN").unwrap())
+    let client: Client = Client::builder()
+        .timeout(config.storage_request_timeout())
+        .build()?;
+    let response = client
+        .post(&config.storage.upload_url)
+        .bearer_auth(token)
         .multipart(form)
         .header("X-NAME", file_name)
         .send()
-        .await
-        .unwrap();
+        .await?;
 
     if response.status() != StatusCode::OK {
         return Err("Failed to upload file to IPFS.".into());
     }
 
-    let response_json: Web3StorageResponse = response.json().await.unwrap();
+    let response_json: Web3StorageResponse = response.json().await?;
     let cid = response_json.cid;
 
     Ok(cid)
 }
+
+/// Whether the configured storage backend can delete uploads.
+pub fn can_delete_from_ipfs() -> bool {
+    config().storage.delete_url.is_some()
+}
+
+pub async fn delete_from_ipfs(cid: &str) -> Result<(), Box<dyn Error>> {
+    observe_ipfs_request("delete", delete(cid)).await
+}
+
+async fn delete(cid: &str) -> Result<(), Box<dyn Error>> {
+    let config = config();
+    let delete_url = config
+        .storage
+        .delete_url
+        .as_ref()
+        .ok_or("Storage backend does not support deletes.")?;
+    let token = config
+        .storage
+        .web3_storage_token
+        .as_ref()
+        .ok_or("No web3.storage token configured.")?;
+
+    let client: Client = Client::builder()
+        .timeout(config.storage_request_timeout())
+        .build()?;
+    let response = client
+        .delete(format!("{}/{}", delete_url, cid))
+        .bearer_auth(token)
+        .send()
+        .await?;
+
+    // Already removed uploads count as deleted
+    if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+        return Err("Failed to delete file from IPFS.".into());
+    }
+
+    Ok(())
+}
+
+/// Checks that the IPFS API answers at all; any non server error response counts as reachable.
+pub async fn check_ipfs_reachable() -> Result<(), Box<dyn Error>> {
+    let client: Client = Client::builder().timeout(Duration::from_secs(5)).build()?;
+    let response = client
+        .post(format!("{}/version", config().storage.ipfs_api_url))
+        .send()
+        .await?;
+
+    if response.status().is_server_error() {
+        return Err("IPFS API is unavailable.".into());
+    }
+
+    Ok(())
+}
@@ -1 +1,10 @@
-pub(crate) mod routes_proofs;
\ No newline at end of file
+pub(crate) mod mw_auth;
+pub(crate) mod mw_metrics;
+pub(crate) mod mw_rate_limit;
+pub(crate) mod routes_api_keys;
+pub(crate) mod routes_batches;
+pub(crate) mod routes_health;
+pub(crate) mod routes_metrics;
+pub(crate) mod routes_proofs;
+pub(crate) mod routes_retention;
+pub(crate) mod routes_search;
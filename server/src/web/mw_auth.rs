@@ -0,0 +1,96 @@
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    db::Db,
+    error::AxumResult,
+    services::api_key::{self, ApiKeyRecord},
+    Error,
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Reads the API key from the `x-api-key` header or an `Authorization: Bearer` header.
+pub fn api_key_from_request<B>(req: &Request<B>) -> Option<String> {
+    let headers = req.headers();
+
+    if let Some(key) = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()) {
+        return Some(key.to_string());
+    }
+
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|key| key.to_string())
+}
+
+/// Rejects requests without a valid API key and exposes the key record to handlers
+/// as an `Extension<ApiKeyRecord>`.
+pub async fn mw_require_api_key<B>(
+    State(db): State<Db>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> AxumResult<Response> {
+    let api_key = authenticate(&db, &req).await?;
+
+    req.extensions_mut().insert(api_key);
+
+    Ok(next.run(req).await)
+}
+
+/// Like [`mw_require_api_key`], but only lets admin keys through.
+pub async fn mw_require_admin_key<B>(
+    State(db): State<Db>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> AxumResult<Response> {
+    let api_key = authenticate(&db, &req).await?;
+    if !api_key.admin {
+        return Err(Error::Forbidden);
+    }
+
+    req.extensions_mut().insert(api_key);
+
+    Ok(next.run(req).await)
+}
+
+async fn authenticate<B>(db: &Db, req: &Request<B>) -> AxumResult<ApiKeyRecord> {
+    // Already validated by the rate limiter
+    if let Some(api_key) = req.extensions().get::<ApiKeyRecord>() {
+        return Ok(api_key.clone());
+    }
+
+    let key = api_key_from_request(req).ok_or(Error::LoginFail)?;
+
+    match api_key::fetch_by_key(db, &key).await {
+        Ok(api_key) => Ok(api_key),
+        Err(_) => Err(Error::LoginFail),
+    }
+}
+
+/// Advisory early reject: checks that creating `requested_sessions` more sessions stays
+/// within the key's daily quota and concurrent session limit before any work is done.
+/// This read is not atomic with the inserts, the authoritative check is the one each
+/// session insert makes in `SessionRepository::create`. Used for batches, so a batch
+/// that clearly exceeds the quota is rejected instead of being partially created.
+pub async fn ensure_api_key_quota(
+    db: &Db,
+    api_key: &ApiKeyRecord,
+    requested_sessions: usize,
+) -> AxumResult<()> {
+    let usage = match api_key::usage(db, api_key).await {
+        Ok(usage) => usage,
+        Err(_) => return Err(Error::FailedToCreate),
+    };
+
+    if !api_key.allows(&usage, requested_sessions) {
+        return Err(Error::QuotaExceeded);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use std::time::Instant;
+
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+
+use crate::metrics::HTTP_REQUEST_DURATION;
+
+/// Records request latency labelled by the matched route template, e.g. `/api/proofs/:id`,
+/// so the metric cardinality does not grow with session IDs.
+pub async fn mw_track_http_metrics<B>(
+    matched_path: Option<MatchedPath>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = matched_path
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+    let started_at = Instant::now();
+
+    let res = next.run(req).await;
+
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route, res.status().as_str()])
+        .observe(started_at.elapsed().as_secs_f64());
+
+    res
+}
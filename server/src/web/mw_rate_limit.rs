@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    config::RateLimitConfig,
+    db::Db,
+    services::api_key::{self, ApiKeyRecord},
+    web::mw_auth::api_key_from_request,
+};
+
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitQuota {
+    /// Maximum number of requests that can be made at once.
+    pub burst: u32,
+    /// Number of requests refilled per second.
+    pub per_second: f64,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RateLimitDecision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    reset_after_secs: u64,
+}
+
+/// Token-bucket rate limiter keyed by API key for requests with a valid one, and by
+/// client IP for all others. The IP is checked first, before any key is looked up.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    db: Db,
+    buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+    ip_quota: RateLimitQuota,
+    api_key_quota: RateLimitQuota,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub fn new(
+        db: Db,
+        ip_quota: RateLimitQuota,
+        api_key_quota: RateLimitQuota,
+        trust_forwarded_for: bool,
+    ) -> Self {
+        RateLimiter {
+            db,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            ip_quota,
+            api_key_quota,
+            trust_forwarded_for,
+        }
+    }
+
+    pub fn from_config(config: &RateLimitConfig, db: Db) -> Self {
+        RateLimiter::new(
+            db,
+            RateLimitQuota {
+                burst: config.ip_burst,
+                per_second: config.ip_per_second,
+            },
+            RateLimitQuota {
+                burst: config.api_key_burst,
+                per_second: config.api_key_per_second,
+            },
+            config.trust_forwarded_for,
+        )
+    }
+
+    fn acquire(&self, bucket_key: String, quota: RateLimitQuota) -> RateLimitDecision {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_BUCKETS && !buckets.contains_key(&bucket_key) {
+            // Forget idle buckets, they would have refilled and behave like new ones
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < IDLE_BUCKET_TTL);
+
+            // Still full, make room by forgetting the bucket that was used the longest ago
+            if buckets.len() >= MAX_TRACKED_BUCKETS {
+                let stalest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(stalest) = stalest {
+                    buckets.remove(&stalest);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(bucket_key).or_insert(TokenBucket {
+            tokens: quota.burst as f64,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.per_second).min(quota.burst as f64);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let missing = quota.burst as f64 - bucket.tokens;
+        let reset_after_secs = if allowed {
+            (missing / quota.per_second).ceil() as u64
+        } else {
+            ((1.0 - bucket.tokens) / quota.per_second).ceil() as u64
+        };
+
+        RateLimitDecision {
+            allowed,
+            limit: quota.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_after_secs,
+        }
+    }
+
+    /// Gives back a token taken by [`RateLimiter::acquire`].
+    fn release(&self, bucket_key: &str, quota: RateLimitQuota) {
+        if let Some(bucket) = self.buckets.lock().unwrap().get_mut(bucket_key) {
+            bucket.tokens = (bucket.tokens + 1.0).min(quota.burst as f64);
+        }
+    }
+
+    fn client_ip(&self, headers: &HeaderMap, addr: &SocketAddr) -> String {
+        if self.trust_forwarded_for {
+            let forwarded_ip = headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .map(|ip| ip.trim().to_string());
+
+            if let Some(ip) = forwarded_ip {
+                return ip;
+            }
+        }
+
+        addr.ip().to_string()
+    }
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: u64) {
+    headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+}
+
+fn apply_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    insert_header(headers, "x-ratelimit-limit", decision.limit as u64);
+    insert_header(headers, "x-ratelimit-remaining", decision.remaining as u64);
+    insert_header(headers, "x-ratelimit-reset", decision.reset_after_secs);
+}
+
+pub async fn mw_rate_limit<B>(
+    State(limiter): State<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    // Every request is charged to its IP first, so a client sending made up keys is
+    // rejected before it can cost a database lookup
+    let ip_bucket = format!("ip:{}", limiter.client_ip(req.headers(), &addr));
+    let mut decision = limiter.acquire(ip_bucket.clone(), limiter.ip_quota);
+
+    // Only validated keys get a bucket of their own, so made up keys can't grow the map.
+    // Their token goes back to the IP, and the record is passed on, sparing the auth
+    // middleware a lookup.
+    if decision.allowed {
+        if let Some(key) = api_key_from_request(&req) {
+            if let Ok(api_key) = api_key::fetch_by_key(&limiter.db, &key).await {
+                limiter.release(&ip_bucket, limiter.ip_quota);
+                decision =
+                    limiter.acquire(format!("key:{}", api_key.key_id()), limiter.api_key_quota);
+                req.extensions_mut().insert::<ApiKeyRecord>(api_key);
+            }
+        }
+    }
+
+    if !decision.allowed {
+        let mut res = (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests").into_response();
+        apply_rate_limit_headers(res.headers_mut(), &decision);
+        insert_header(res.headers_mut(), "retry-after", decision.reset_after_secs);
+        return res;
+    }
+
+    let mut res = next.run(req).await;
+    apply_rate_limit_headers(res.headers_mut(), &decision);
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connect_test_db;
+
+    const QUOTA: RateLimitQuota = RateLimitQuota {
+        burst: 2,
+        per_second: 1.0,
+    };
+
+    #[tokio::test]
+    async fn test_bucket_count_is_capped() {
+        let limiter = RateLimiter::new(connect_test_db().await, QUOTA, QUOTA, false);
+
+        for i in 0..MAX_TRACKED_BUCKETS + 10 {
+            limiter.acquire(format!("ip:{}", i), QUOTA);
+        }
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_BUCKETS);
+        assert!(buckets.contains_key(&format!("ip:{}", MAX_TRACKED_BUCKETS + 9)));
+    }
+
+    #[tokio::test]
+    async fn test_released_tokens_go_back_to_the_bucket() {
+        let limiter = RateLimiter::new(connect_test_db().await, QUOTA, QUOTA, false);
+
+        limiter.acquire("ip:1".into(), QUOTA);
+        limiter.release("ip:1", QUOTA);
+        limiter.acquire("ip:1".into(), QUOTA);
+        let decision = limiter.acquire("ip:1".into(), QUOTA);
+
+        assert!(decision.allowed);
+        assert!(!limiter.acquire("ip:1".into(), QUOTA).allowed);
+    }
+}
@@ -0,0 +1,46 @@
+use axum::{
+    extract::{Json, State},
+    middleware,
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    db::Db,
+    error::AxumResult,
+    services::api_key::{self, NewApiKey},
+    state::AppState,
+    web::mw_auth::mw_require_admin_key,
+    Error,
+};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/api/keys",
+            post(api_key_create).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                mw_require_admin_key,
+            )),
+        )
+        .with_state(state)
+}
+
+/// The response is the only place the new key is ever shown, only its hash is stored.
+async fn api_key_create(
+    State(db): State<Db>,
+    Json(payload): Json<NewApiKey>,
+) -> AxumResult<Json<Value>> {
+    match api_key::create(&db, &payload).await {
+        Ok((key, record)) => Ok(Json(json!({
+            "id": record.key_id(),
+            "key": key,
+            "name": record.name,
+            "daily_quota": record.daily_quota,
+            "max_concurrent_sessions": record.max_concurrent_sessions,
+            "admin": record.admin,
+        }))),
+        Err(_) => return Err(Error::FailedToCreate),
+    }
+}
@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Extension, Json, Path, State},
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    db::Db,
+    error::AxumResult,
+    services::{
+        api_key::ApiKeyRecord, proof_batch, proof_session::ProofSessionArgument,
+        session_repository::SharedSessionRepository, shutdown,
+    },
+    state::AppState,
+    web::mw_auth::{ensure_api_key_quota, mw_require_api_key},
+    Error,
+};
+
+#[derive(Debug, Deserialize)]
+struct ProofBatchPayload {
+    image_cid: String,
+    arguments: Vec<Vec<ProofSessionArgument>>,
+    /// Hex encoded private input of each session, in the order of `arguments`
+    #[serde(default)]
+    private_inputs: Vec<Option<String>>,
+}
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/api/proofs/batch",
+            post(api_proof_batch_create).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                mw_require_api_key,
+            )),
+        )
+        .route("/api/batches/:id", get(api_fetch_proof_batch_status))
+        .with_state(state)
+}
+
+async fn api_proof_batch_create(
+    State(db): State<Db>,
+    State(sessions): State<SharedSessionRepository>,
+    Extension(api_key): Extension<ApiKeyRecord>,
+    Json(payload): Json<ProofBatchPayload>,
+) -> AxumResult<Json<Value>> {
+    if !shutdown::is_accepting_sessions() {
+        return Err(Error::ShuttingDown);
+    }
+
+    ensure_api_key_quota(&db, &api_key, payload.arguments.len()).await?;
+
+    let private_inputs = payload
+        .private_inputs
+        .iter()
+        .map(|private_input| private_input.as_ref().map(hex::decode).transpose())
+        .collect::<Result<Vec<Option<Vec<u8>>>, _>>()
+        .map_err(|_| Error::FailedToCreate)?;
+
+    match proof_batch::create(
+        &sessions,
+        &payload.image_cid,
+        &payload.arguments,
+        private_inputs,
+        Some(&api_key),
+    )
+    .await
+    {
+        Ok(proof_batch) => Ok(Json(json!({
+            "batch_id": proof_batch.batch_id,
+            "session_ids": proof_batch.session_ids,
+            "creation": proof_batch.creation,
+            "creation_error": proof_batch.creation_error
+        }))),
+        Err(_) => return Err(Error::FailedToCreate),
+    }
+}
+
+async fn api_fetch_proof_batch_status(
+    State(sessions): State<SharedSessionRepository>,
+    Path(id): Path<String>,
+) -> AxumResult<Json<Value>> {
+    match proof_batch::fetch(&sessions, &id).await {
+        Ok(proof_batch) => Ok(Json(json!(proof_batch))),
+        Err(_) => return Err(Error::NotFound),
+    }
+}
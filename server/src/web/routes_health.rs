@@ -0,0 +1,108 @@
+use std::{
+    panic,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    routing::get,
+    Router,
+};
+use once_cell::sync::Lazy;
+use risc0_zkvm::default_prover;
+use serde_json::{json, Value};
+use tokio::task;
+
+use crate::{db::Db, services::shutdown, state::AppState, utils::ipfs::check_ipfs_reachable};
+
+/// Probes run every few seconds, the content store is asked at most this often.
+const CONTENT_STORE_CHECK_TTL: Duration = Duration::from_secs(15);
+
+static CONTENT_STORE_CHECK: Lazy<Mutex<Option<(Instant, bool)>>> = Lazy::new(|| Mutex::new(None));
+
+/// The prover is selected at runtime, e.g. through `RISC0_PROVER`, and selecting one that
+/// isn't built in panics. Holds the name of the prover proofs run on, if any. It can't
+/// change while the server runs, so it is checked once, see [`check_prover`].
+static PROVER: Lazy<Option<String>> =
+    Lazy::new(|| panic::catch_unwind(|| default_prover().get_name()).ok());
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/healthz", get(api_healthz))
+        .route("/readyz", get(api_readyz))
+        .route("/version", get(api_version))
+        .with_state(state)
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "cuda") {
+        features.push("cuda");
+    }
+    if cfg!(feature = "metal") {
+        features.push("metal");
+    }
+
+    features
+}
+
+async fn api_healthz() -> &'static str {
+    "ok"
+}
+
+async fn content_store_reachable() -> bool {
+    let cached = *CONTENT_STORE_CHECK.lock().unwrap();
+    if let Some((checked_at, reachable)) = cached {
+        if checked_at.elapsed() < CONTENT_STORE_CHECK_TTL {
+            return reachable;
+        }
+    }
+
+    let reachable = check_ipfs_reachable().await.is_ok();
+    *CONTENT_STORE_CHECK.lock().unwrap() = Some((Instant::now(), reachable));
+
+    reachable
+}
+
+/// Selects the prover, called at startup so readiness probes only read the result.
+pub async fn check_prover() -> Option<String> {
+    task::spawn_blocking(|| PROVER.clone()).await.ok().flatten()
+}
+
+async fn api_readyz(State(db): State<Db>) -> (StatusCode, Json<Value>) {
+    let database = db.query("INFO FOR DB").await.is_ok();
+    let content_store = content_store_reachable().await;
+    let prover = PROVER.clone();
+    let accepting_sessions = shutdown::is_accepting_sessions();
+
+    let status = if database && content_store && prover.is_some() && accepting_sessions {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(json!({
+            "ready": status == StatusCode::OK,
+            "checks": {
+                "database": database,
+                "content_store": content_store,
+                "prover": prover.is_some(),
+                "accepting_sessions": accepting_sessions,
+            },
+            "prover": prover,
+            "running_sessions": shutdown::running_sessions(),
+        })),
+    )
+}
+
+async fn api_version() -> Json<Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "risc0_zkvm_version": env!("RISC0_ZKVM_VERSION"),
+        "features": enabled_features(),
+    }))
+}
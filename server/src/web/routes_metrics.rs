@@ -0,0 +1,14 @@
+use axum::{http::header::CONTENT_TYPE, response::IntoResponse, routing::get, Router};
+
+use crate::metrics;
+
+pub fn routes() -> Router {
+    Router::new().route("/metrics", get(api_metrics))
+}
+
+async fn api_metrics() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::gather(),
+    )
+}
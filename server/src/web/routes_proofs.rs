@@ -1,5 +1,9 @@
 use axum::{
-    extract::{Json, Path},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Extension, Json, Path, Query, State},
+    http::header,
+    middleware,
+    response::IntoResponse,
     routing::{get, post},
     Router,
 };
@@ -7,8 +11,17 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 
 use crate::{
+    config::config,
     error::AxumResult,
-    services::proof_session::{self, ProofSessionArgument},
+    services::{
+        api_key::ApiKeyRecord,
+        proof_session::{self, NotSessionOwner, ProofSessionArgument, ProofSessionFilter},
+        session_bundle,
+        session_repository::{QuotaExceeded, SharedSessionRepository},
+        shutdown,
+    },
+    state::AppState,
+    web::mw_auth::mw_require_api_key,
     Error,
 };
 
@@ -16,42 +29,111 @@ use crate::{
 struct ProofSessionPayload {
     image_cid: String,
     arguments: Vec<ProofSessionArgument>,
+    /// Hex encoded bytes for images importing `input`, never stored with the session
+    #[serde(default)]
+    private_input: Option<String>,
 }
 
-pub fn routes() -> Router {
+pub fn routes(state: AppState) -> Router {
     Router::new()
-        .route("/api/proofs", post(api_proof_create))
+        .route("/api/proofs", get(api_list_proof))
+        .route(
+            "/api/proofs",
+            post(api_proof_create).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                mw_require_api_key,
+            )),
+        )
         .route(
             "/api/proofs/by-image/:image_cid",
             get(api_list_proof_by_image),
         )
+        .route(
+            "/api/proofs/import",
+            post(api_proof_import)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    mw_require_api_key,
+                ))
+                .layer(DefaultBodyLimit::max(config().limits.max_bundle_size_bytes)),
+        )
         .route("/api/proofs/:id", get(api_fetch_proof_status))
+        .route("/api/proofs/:id/export", get(api_proof_export))
         .route("/api/proofs/:id/verify", get(api_fetch_proof_verification))
+        .route(
+            "/api/proofs/:id/pin",
+            post(api_proof_pin).delete(api_proof_unpin).route_layer(
+                middleware::from_fn_with_state(state.clone(), mw_require_api_key),
+            ),
+        )
+        .with_state(state)
 }
 
-async fn api_fetch_proof_status(Path(id): Path<String>) -> AxumResult<Json<Value>> {
-    match proof_session::fetch(&id).await {
+async fn api_fetch_proof_status(
+    State(sessions): State<SharedSessionRepository>,
+    Path(id): Path<String>,
+) -> AxumResult<Json<Value>> {
+    match proof_session::fetch(&sessions, &id).await {
         Ok(proof_session) => Ok(Json(json!(proof_session))),
         Err(_) => return Err(Error::NotFound),
     }
 }
 
-async fn api_list_proof_by_image(Path(image_cid): Path<String>) -> AxumResult<Json<Value>> {
-    match proof_session::list_by_image(&image_cid).await {
+async fn api_list_proof(
+    State(sessions): State<SharedSessionRepository>,
+    Query(filter): Query<ProofSessionFilter>,
+) -> AxumResult<Json<Value>> {
+    match proof_session::list(&sessions, None, &filter).await {
         Ok(proof_sessions) => Ok(Json(json!(proof_sessions))),
         Err(_) => return Err(Error::NotFound),
     }
 }
 
-async fn api_proof_create(Json(payload): Json<ProofSessionPayload>) -> AxumResult<Json<Value>> {
-    match proof_session::create(&payload.image_cid, &payload.arguments).await {
+async fn api_list_proof_by_image(
+    State(sessions): State<SharedSessionRepository>,
+    Path(image_cid): Path<String>,
+    Query(filter): Query<ProofSessionFilter>,
+) -> AxumResult<Json<Value>> {
+    match proof_session::list_by_image(&sessions, &image_cid, &filter).await {
+        Ok(proof_sessions) => Ok(Json(json!(proof_sessions))),
+        Err(_) => return Err(Error::NotFound),
+    }
+}
+
+async fn api_proof_create(
+    State(sessions): State<SharedSessionRepository>,
+    Extension(api_key): Extension<ApiKeyRecord>,
+    Json(payload): Json<ProofSessionPayload>,
+) -> AxumResult<Json<Value>> {
+    if !shutdown::is_accepting_sessions() {
+        return Err(Error::ShuttingDown);
+    }
+
+    let private_input = match &payload.private_input {
+        Some(private_input) => Some(hex::decode(private_input).map_err(|_| Error::FailedToCreate)?),
+        None => None,
+    };
+
+    match proof_session::create(
+        &sessions,
+        &payload.image_cid,
+        &payload.arguments,
+        private_input,
+        Some(&api_key),
+    )
+    .await
+    {
         Ok(proof_session) => Ok(Json(json!({ "session_id": proof_session.session_id }))),
+        Err(err) if err.is::<QuotaExceeded>() => Err(Error::QuotaExceeded),
         Err(_) => return Err(Error::FailedToCreate),
     }
 }
 
-async fn api_fetch_proof_verification(Path(id): Path<String>) -> AxumResult<Json<Value>> {
-    match proof_session::verify(&id).await {
+async fn api_fetch_proof_verification(
+    State(sessions): State<SharedSessionRepository>,
+    Path(id): Path<String>,
+) -> AxumResult<Json<Value>> {
+    match proof_session::verify(&sessions, &id).await {
         Ok(proof_session_result) => Ok(Json(json!({
             "verified": true,
             "result": proof_session_result
@@ -59,3 +141,62 @@ async fn api_fetch_proof_verification(Path(id): Path<String>) -> AxumResult<Json
         Err(_) => return Err(Error::FailedToVerify),
     }
 }
+
+async fn api_proof_pin(
+    State(sessions): State<SharedSessionRepository>,
+    Extension(api_key): Extension<ApiKeyRecord>,
+    Path(id): Path<String>,
+) -> AxumResult<Json<Value>> {
+    match proof_session::set_pinned(&sessions, &id, true, &api_key).await {
+        Ok(proof_session) => Ok(Json(json!({ "pinned": proof_session.pinned }))),
+        Err(err) if err.is::<NotSessionOwner>() => Err(Error::Forbidden),
+        Err(_) => return Err(Error::NotFound),
+    }
+}
+
+async fn api_proof_unpin(
+    State(sessions): State<SharedSessionRepository>,
+    Extension(api_key): Extension<ApiKeyRecord>,
+    Path(id): Path<String>,
+) -> AxumResult<Json<Value>> {
+    match proof_session::set_pinned(&sessions, &id, false, &api_key).await {
+        Ok(proof_session) => Ok(Json(json!({ "pinned": proof_session.pinned }))),
+        Err(err) if err.is::<NotSessionOwner>() => Err(Error::Forbidden),
+        Err(_) => return Err(Error::NotFound),
+    }
+}
+
+async fn api_proof_export(
+    State(sessions): State<SharedSessionRepository>,
+    Path(id): Path<String>,
+) -> AxumResult<impl IntoResponse> {
+    match session_bundle::export(&sessions, &id).await {
+        Ok(bundle) => Ok((
+            [
+                (header::CONTENT_TYPE, "application/gzip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.tar.gz\"", id),
+                ),
+            ],
+            bundle,
+        )),
+        Err(_) => return Err(Error::NotFound),
+    }
+}
+
+async fn api_proof_import(
+    State(sessions): State<SharedSessionRepository>,
+    Extension(api_key): Extension<ApiKeyRecord>,
+    bundle: Bytes,
+) -> AxumResult<Json<Value>> {
+    if !shutdown::is_accepting_sessions() {
+        return Err(Error::ShuttingDown);
+    }
+
+    let api_key_id = api_key.key_id();
+    match session_bundle::import(&sessions, bundle.into(), Some(&api_key_id)).await {
+        Ok(proof_session) => Ok(Json(json!({ "session_id": proof_session.session_id }))),
+        Err(_) => return Err(Error::FailedToVerify),
+    }
+}
@@ -0,0 +1,38 @@
+use axum::{
+    extract::{Json, State},
+    middleware,
+    routing::get,
+    Router,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    error::AxumResult,
+    services::{retention, session_repository::SharedSessionRepository},
+    state::AppState,
+    web::mw_auth::mw_require_admin_key,
+    Error,
+};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/api/retention/report",
+            get(api_retention_report).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                mw_require_admin_key,
+            )),
+        )
+        .with_state(state)
+}
+
+/// Dry run of the retention policy, listing what the next run would collect. Admin keys only,
+/// as the report lists sessions of every key.
+async fn api_retention_report(
+    State(sessions): State<SharedSessionRepository>,
+) -> AxumResult<Json<Value>> {
+    match retention::collect(&sessions, true).await {
+        Ok(report) => Ok(Json(json!(report))),
+        Err(_) => return Err(Error::NotFound),
+    }
+}
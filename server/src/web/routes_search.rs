@@ -0,0 +1,35 @@
+use axum::{
+    extract::{Json, Query, State},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    error::AxumResult,
+    services::{search, session_repository::SharedSessionRepository},
+    state::AppState,
+    Error,
+};
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/api/search", get(api_search))
+        .with_state(state)
+}
+
+async fn api_search(
+    State(sessions): State<SharedSessionRepository>,
+    Query(query): Query<SearchQuery>,
+) -> AxumResult<Json<Value>> {
+    match search::search(&sessions, &query.q).await {
+        Ok(results) => Ok(Json(json!(results))),
+        Err(_) => return Err(Error::NotFound),
+    }
+}